@@ -0,0 +1,42 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+const LINES: usize = 100_000;
+
+// Log-like lines where one in a hundred is a connection error, some of them
+// with a typo
+fn corpus() -> String {
+    let mut contents = String::new();
+    for line in 0..LINES {
+        if line % 100 == 0 {
+            let error = if line % 300 == 0 { "conection refused" } else { "connection refused" };
+            contents.push_str(&format!("ERROR request {line}: {error} by upstream\n"));
+        } else {
+            contents.push_str(&format!("INFO request {line} handled by worker {}\n", line % 16));
+        }
+    }
+    contents
+}
+
+fn bench_fuzzy(c: &mut Criterion) {
+    let contents = corpus();
+
+    let mut group = c.benchmark_group("fuzzy");
+    group.throughput(Throughput::Bytes(contents.len() as u64));
+    group.bench_function("exact", |b| {
+        b.iter(|| minigrep::search(black_box("connection refused"), &contents))
+    });
+    for distance in [1, 2, 4] {
+        group.bench_function(format!("distance_{distance}"), |b| {
+            b.iter(|| minigrep::search_fuzzy(black_box("connection refused"), &contents, distance))
+        });
+    }
+    // The pieces of a short pattern are on every line, so every line goes
+    // through the full edit distance table
+    group.bench_function("unfiltered", |b| {
+        b.iter(|| minigrep::search_fuzzy(black_box("wroker"), &contents, 2))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_fuzzy);
+criterion_main!(benches);
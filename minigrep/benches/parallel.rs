@@ -0,0 +1,54 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use minigrep::Config;
+
+const FILES: usize = 200;
+const LINES_PER_FILE: usize = 5_000;
+
+// A directory of log-like files where roughly one line in a hundred matches
+fn corpus() -> PathBuf {
+    let root = env::temp_dir().join("minigrep-bench-parallel");
+    if root.is_dir() {
+        return root;
+    }
+
+    fs::create_dir_all(&root).unwrap();
+    for file in 0..FILES {
+        let mut contents = String::new();
+        for line in 0..LINES_PER_FILE {
+            let level = if line % 100 == 0 { "ERROR" } else { "INFO" };
+            contents.push_str(&format!("{level} request {line} handled by worker {file}\n"));
+        }
+        fs::write(root.join(format!("{file:03}.log")), contents).unwrap();
+    }
+    root
+}
+
+fn search(root: &Path, threads: usize) {
+    let config = Config {
+        patterns: vec![String::from("ERROR")],
+        paths: vec![root.to_string_lossy().into_owned()],
+        threads: Some(threads),
+        ..Config::default()
+    };
+    minigrep::search_to(config, &mut io::sink()).unwrap();
+}
+
+fn bench_threads(c: &mut Criterion) {
+    let root = corpus();
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+
+    let mut group = c.benchmark_group("search_directory");
+    group.sample_size(20);
+    group.bench_function("single_thread", |b| b.iter(|| search(&root, 1)));
+    group.bench_function(format!("{cpus}_threads"), |b| b.iter(|| search(&root, cpus)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_threads);
+criterion_main!(benches);
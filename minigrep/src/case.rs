@@ -0,0 +1,109 @@
+use std::iter;
+use std::ops::Range;
+
+use caseless::Caseless;
+
+// Full Unicode case folding, so "Straße" and "STRASSE" fold to the same
+// "strasse". This is the locale independent folding: Turkish dotted 'İ' folds
+// to 'i' followed by U+0307 COMBINING DOT ABOVE.
+pub fn fold(s: &str) -> String {
+    s.chars().default_case_fold().collect()
+}
+
+// Folds `line` and records, for every byte of the result, the range of the
+// character in `line` it came from. Folding can change the length of a
+// character, so offsets in the folded line can't be used on `line` directly.
+pub fn fold_with_origins(line: &str) -> (String, Vec<Range<usize>>) {
+    let mut folded = String::with_capacity(line.len());
+    let mut origins = Vec::with_capacity(line.len());
+
+    for (start, c) in line.char_indices() {
+        let origin = start..start + c.len_utf8();
+        for f in iter::once(c).default_case_fold() {
+            folded.push(f);
+            origins.resize(folded.len(), origin.clone());
+        }
+    }
+
+    (folded, origins)
+}
+
+// Maps ranges in the folded line back to `line`. A match can start or end in
+// the middle of a folded character ("s" in "ß" -> "ss"), in which case the
+// whole character is included, and ranges that end up overlapping are merged.
+pub fn original_spans(
+    origins: &[Range<usize>],
+    folded_spans: impl Iterator<Item = Range<usize>>,
+) -> Vec<Range<usize>> {
+    let mut spans: Vec<Range<usize>> = Vec::new();
+
+    for folded in folded_spans.filter(|span| !span.is_empty()) {
+        let span = origins[folded.start].start..origins[folded.end - 1].end;
+        match spans.last_mut() {
+            Some(last) if span.start < last.end => last.end = last.end.max(span.end),
+            _ => spans.push(span),
+        }
+    }
+
+    spans
+}
+
+// Smart case: a query only matches case-sensitively when it has an uppercase
+// letter. In a regex the letter after a backslash is syntax (`\S`, `\W`,
+// `\p{Lu}`), not part of the text to find.
+pub fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            // Unicode classes are `\pL` or `\p{Lu}`
+            if let Some('p' | 'P') = chars.next() {
+                if chars.next_if_eq(&'{').is_some() {
+                    chars.by_ref().find(|&c| c == '}');
+                } else {
+                    chars.next();
+                }
+            }
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folding_changes_length() {
+        assert_eq!("strasse", fold("Straße"));
+        assert_eq!(fold("STRASSE"), fold("straße"));
+        assert_eq!("i\u{307}stanbul", fold("İstanbul"));
+    }
+
+    #[test]
+    fn spans_in_original_line() {
+        let line = "Die Straße";
+        let (folded, origins) = fold_with_origins(line);
+        let spans = original_spans(&origins, folded.match_indices("strasse").map(|(i, m)| i..i + m.len()));
+        assert_eq!(vec![4..11], spans);
+        assert_eq!("Straße", &line[spans[0].clone()]);
+    }
+
+    #[test]
+    fn partial_character_matches_are_merged() {
+        let (folded, origins) = fold_with_origins("aß");
+        let spans = original_spans(&origins, folded.match_indices('s').map(|(i, _)| i..i + 1));
+        assert_eq!(vec![1..3], spans);
+    }
+
+    #[test]
+    fn smart_case_detection() {
+        assert!(has_uppercase("Error", false));
+        assert!(!has_uppercase("error", false));
+        assert!(!has_uppercase(r"\S+\d", true));
+        assert!(has_uppercase(r"\S+\D", false));
+        assert!(has_uppercase(r"\sÉ", true));
+        assert!(!has_uppercase(r"\p{Lu}\PL", true));
+    }
+}
@@ -0,0 +1,926 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{case, encoding, Aggregate, Config, Error, Expr, STDIN_PATH};
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY [PATH]...
+       minigrep [OPTIONS] -e PATTERN... [PATH]...
+       minigrep [OPTIONS] -f FILE [PATH]...
+       minigrep index [DIR]";
+
+const HELP: &str = "\
+Search for QUERY in each PATH and print the matching lines. With -e or -f
+there is no QUERY argument and a line matches when any PATTERN matches.
+Directories are searched recursively, optionally filtered by --glob. With no
+PATH, or when PATH is -, standard input is read.
+
+'minigrep index DIR' builds a trigram index of DIR (the current directory by
+default) in DIR/.minigrep-index, and running it again only rereads the files
+that changed. Searches with --use-index then skip the files of DIR that can't
+match. To search for the word index, use -e index.
+
+Options:
+  -i, --ignore-case           ignore case distinctions (default when IGNORE_CASE is set).
+                              With -E a character only matches one other
+                              character, so STRASSE doesn't match straße
+  -s, --case-sensitive        match case exactly, overriding IGNORE_CASE
+  -S, --smart-case            ignore case unless QUERY has an uppercase letter
+  -E, --regex                 treat QUERY as a regular expression (default when REGEX is set)
+  -e, --regexp PATTERN        search for PATTERN, can be given more than once
+  -f, --file FILE             search for the patterns in FILE, one per line
+      --boolean               QUERY (and each PATTERN) combines terms with AND, OR
+                              and NOT, as in 'error AND (db OR cache) AND NOT
+                              timeout'. \"Quote\" terms with spaces or parentheses
+      --log-format FORMAT     parse each line as json, logfmt or auto (JSON when
+                              it starts with '{'), and match each PATTERN as a
+                              condition on its fields: level=error, level!=debug,
+                              .request.path~/api/ (regex), msg!~timeout. Lines
+                              that can't be parsed are skipped
+      --select FIELDS         with --log-format, print only these comma separated
+                              fields of selected lines, as logfmt
+      --report-invalid        warn about lines --log-format can't parse
+      --extract TEMPLATE      print TEMPLATE instead of each selected line, with
+                              $1 or ${name} a capture group of the match (-E)
+      --field FIELD           print only this field of each selected line: a
+                              number from 1, or a field name with --log-format
+      --delimiter DELIM       split --field by DELIM instead of whitespace
+      --aggregate KIND        count the --extract or --field values of all
+                              selected lines and print them, most common first,
+                              as counts (like 'sort | uniq -c') or a histogram
+      --top NUM               print only the NUM most common values, implies
+                              --aggregate counts
+      --fuzzy DIST            match parts of lines within DIST edits (inserted,
+                              deleted or replaced characters) of a pattern
+  -w, --word-regexp           only match whole words
+  -x, --line-regexp           only match whole lines
+  -U, --multiline             match against the whole input, so a match can span
+                              lines; every line it touches is printed. With -E,
+                              ^ and $ match at line breaks and \\s matches them.
+                              -m limits the matches instead of the lines
+  -v, --invert-match          select non-matching lines
+  -c, --count                 print only a count of selected lines per file
+  -l, --files-with-matches    print only the names of files with selected lines
+  -L, --files-without-match   print only the names of files without selected lines
+  -m, --max-count NUM         stop reading a file after NUM selected lines
+  -q, --quiet                 print nothing, only exit with status 0 when a line
+                              is selected and 1 when none is
+  -n, --line-number           prefix each line with its line number
+  -b, --byte-offset           prefix each line with its byte offset
+  -A, --after-context NUM     print NUM lines of trailing context
+  -B, --before-context NUM    print NUM lines of leading context
+  -C, --context NUM           print NUM lines of leading and trailing context
+  -g, --glob GLOB             only search files matching GLOB ('*.rs'), or skip
+                              them when it starts with ! ('!target/**')
+      --use-index             only search the files of indexed directories that
+                              can have a match, see 'minigrep index'
+  -z, --search-zip            search the contents of gzip, zstd and bzip2
+                              compressed input
+      --replace TEXT          print matching lines with each match replaced by TEXT,
+                              in which $1 or ${name} is a capture group with -E
+      --write                 apply --replace to the files instead of printing
+      --diff                  print what --replace changes as a unified diff
+      --encoding ENC          read input without a BOM as ENC (latin1, utf-16le, ...),
+                              auto (the default) reads it as UTF-8. Bytes that
+                              aren't valid are replaced, not an error
+      --follow                search the lines appended to FILE as they arrive,
+                              like 'tail -f', until -m lines are selected. Keeps
+                              going when the file is truncated or replaced. -n
+                              and -b count from where following started
+  -j, --threads NUM           search files on NUM threads (default: one per CPU)
+      --json                  print results as JSON Lines: begin, match, context
+                              and end records for each file with matches
+      --color[=WHEN]          highlight matches, WHEN is auto (the default when
+                              no WHEN is given), always or never. auto only uses
+                              color on a terminal and when NO_COLOR is not set
+  -h, --help                  print this help and exit
+  -V, --version               print version information and exit";
+
+// Short options that don't take a value, with the long name they expand to
+const FLAGS: &[(char, &str)] = &[
+    ('i', "ignore-case"),
+    ('s', "case-sensitive"),
+    ('S', "smart-case"),
+    ('E', "regex"),
+    ('w', "word-regexp"),
+    ('x', "line-regexp"),
+    ('U', "multiline"),
+    ('v', "invert-match"),
+    ('c', "count"),
+    ('l', "files-with-matches"),
+    ('L', "files-without-match"),
+    ('q', "quiet"),
+    ('n', "line-number"),
+    ('b', "byte-offset"),
+    ('z', "search-zip"),
+    ('h', "help"),
+    ('V', "version"),
+];
+
+// Options that give the patterns, there is no QUERY argument when they are used
+const PATTERN_OPTIONS: &[&str] = &["regexp", "file"];
+
+// Short options that take a value
+const VALUE_OPTIONS: &[(char, &str)] = &[
+    ('A', "after-context"),
+    ('B', "before-context"),
+    ('C', "context"),
+    ('e', "regexp"),
+    ('f', "file"),
+    ('g', "glob"),
+    ('j', "threads"),
+    ('m', "max-count"),
+];
+
+// Options that take a value and only have a long name
+const LONG_VALUE_OPTIONS: &[&str] = &[
+    "encoding",
+    "replace",
+    "fuzzy",
+    "log-format",
+    "select",
+    "extract",
+    "field",
+    "delimiter",
+    "aggregate",
+    "top",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    Help,
+    Version,
+    Usage(String),
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgsError::Help => write!(f, "{USAGE}\n\n{HELP}"),
+            ArgsError::Version => write!(f, "minigrep {}", env!("CARGO_PKG_VERSION")),
+            ArgsError::Usage(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl error::Error for ArgsError {}
+
+fn usage(msg: String) -> ArgsError {
+    ArgsError::Usage(msg)
+}
+
+// Parses the command line on top of `config`, which carries the defaults
+// taken from the environment. Flags always win over those defaults.
+pub fn parse(args: &[String], mut config: Config) -> Result<Config, Error> {
+    let mut positional = Vec::new();
+    let mut explicit_patterns = false;
+    let mut args = args.iter().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref().cloned());
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+
+            if name == "color" || name == "colour" {
+                // Unlike other options the value can only be attached, as in grep
+                let value = value.as_deref().unwrap_or("auto");
+                config.color = value.parse().map_err(usage)?;
+            } else if VALUE_OPTIONS.iter().any(|&(_, l)| l == name)
+                || LONG_VALUE_OPTIONS.contains(&name)
+            {
+                let value = value.or_else(|| args.next().cloned());
+                set_value(&mut config, name, &format!("--{name}"), value)?;
+                explicit_patterns |= PATTERN_OPTIONS.contains(&name);
+            } else if value.is_some() {
+                return Err(usage(format!("option '--{name}' doesn't take a value")).into());
+            } else {
+                set_flag(&mut config, name, &format!("--{name}"))?;
+            }
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            for (index, short) in arg.char_indices().skip(1) {
+                if let Some(&(_, long)) = VALUE_OPTIONS.iter().find(|&&(s, _)| s == short) {
+                    // The value is either attached (`-A3`) or the next argument
+                    let attached = &arg[index + short.len_utf8()..];
+                    let value = if attached.is_empty() {
+                        args.next().cloned()
+                    } else {
+                        Some(attached.to_string())
+                    };
+                    set_value(&mut config, long, &format!("-{short}"), value)?;
+                    explicit_patterns |= PATTERN_OPTIONS.contains(&long);
+                    break;
+                }
+
+                match FLAGS.iter().find(|&&(s, _)| s == short) {
+                    Some(&(_, long)) => set_flag(&mut config, long, &format!("-{short}"))?,
+                    None => return Err(usage(format!("unknown option '-{short}'")).into()),
+                }
+            }
+        } else {
+            positional.push(arg.clone());
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    if !explicit_patterns {
+        let query = positional
+            .next()
+            .ok_or_else(|| usage(String::from("missing required argument QUERY")))?;
+        config.patterns.push(query);
+    }
+    config.paths = positional.collect();
+    if config.paths.is_empty() {
+        config.paths.push(String::from(STDIN_PATH));
+    }
+
+    prepare(config)
+}
+
+// Checks that the options of `config` go together, and parses and compiles
+// its patterns once, for the command line and `SearcherBuilder` alike
+pub fn prepare(mut config: Config) -> Result<Config, Error> {
+    let list_files = config.files_with_matches || config.files_without_match;
+    if config.json && (config.count || list_files) {
+        return Err(usage(String::from(
+            "--json can't be combined with --count, --files-with-matches or --files-without-match",
+        ))
+        .into());
+    }
+
+    check_replace(&config)?;
+    check_follow(&config)?;
+    check_multiline(&config)?;
+    check_log_format(&config)?;
+    check_extract(&mut config)?;
+    if config.fuzzy.is_some() && (config.regex || config.word_regexp || config.line_regexp) {
+        return Err(usage(String::from(
+            "--fuzzy can't be combined with --regex, --word-regexp or --line-regexp",
+        ))
+        .into());
+    }
+
+    if config.boolean {
+        config.query = Some(parse_queries(&config.patterns)?);
+    }
+
+    if config.smart_case {
+        let regex = config.regex;
+        // The operators of a query are uppercase, only its terms count
+        let terms: Vec<&str> = match &config.query {
+            Some(query) => query.terms(),
+            None => config.patterns.iter().map(String::as_str).collect(),
+        };
+        config.ignore_case = !terms.iter().any(|p| case::has_uppercase(p, regex));
+    }
+
+    // Without patterns nothing matches, which the literal matcher handles.
+    // The terms of a query, and field conditions, are compiled by the matcher.
+    let whole_patterns = !config.boolean && config.log_format.is_none();
+    if config.regex && !config.patterns.is_empty() && whole_patterns {
+        let pattern = Config::compile_patterns(
+            &config.patterns,
+            config.ignore_case,
+            config.line_regexp,
+            config.multiline,
+        )
+        .map_err(Error::Pattern)?;
+        config.pattern = Some(pattern);
+    }
+
+    Ok(config)
+}
+
+// The subcommand that builds an index, instead of searching
+pub const INDEX_COMMAND: &str = "index";
+
+// `minigrep index [DIR]`, returns DIR
+pub fn parse_index(args: &[String]) -> Result<PathBuf, Error> {
+    let mut root = None;
+    for arg in args.iter().skip(2) {
+        match arg.as_str() {
+            "-h" | "--help" => return Err(ArgsError::Help.into()),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(usage(format!("unknown option '{arg}' for 'minigrep index'")).into());
+            }
+            _ if root.is_some() => {
+                return Err(usage(String::from("'minigrep index' takes one directory")).into());
+            }
+            _ => root = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let root = root.unwrap_or_else(|| PathBuf::from("."));
+    if !root.is_dir() {
+        return Err(usage(format!("'{}' is not a directory", root.display())).into());
+    }
+    Ok(root)
+}
+
+// A line matches when any of the queries does
+fn parse_queries(queries: &[String]) -> Result<Expr, Error> {
+    let mut parsed = Vec::with_capacity(queries.len());
+    for query in queries {
+        parsed.push(Expr::parse(query).map_err(|e| Error::Pattern(e.render(query)))?);
+    }
+    Ok(if parsed.len() == 1 { parsed.remove(0) } else { Expr::Or(parsed) })
+}
+
+// --write and --diff work on whole files, which they have to be able to write
+// back as they were
+fn check_replace(config: &Config) -> Result<(), ArgsError> {
+    let rewrite = config.write || config.diff;
+    if rewrite && config.replace.is_none() {
+        return Err(usage(String::from("--write and --diff need --replace")));
+    }
+    if config.replace.is_some() && config.invert_match {
+        return Err(usage(String::from("--replace can't be combined with --invert-match")));
+    }
+    if config.replace.is_some() && config.boolean {
+        return Err(usage(String::from("--replace can't be combined with --boolean")));
+    }
+    if rewrite && config.paths.iter().any(|p| p == STDIN_PATH) {
+        return Err(usage(String::from("--write and --diff can't be used on standard input")));
+    }
+    if rewrite && config.search_zip {
+        return Err(usage(String::from("--write and --diff can't be combined with --search-zip")));
+    }
+    Ok(())
+}
+
+// --follow reads one file that never ends, so there is no count or list of
+// files to print at the end of it
+fn check_follow(config: &Config) -> Result<(), ArgsError> {
+    if !config.follow {
+        return Ok(());
+    }
+    if config.count || config.files_without_match || config.write || config.diff {
+        return Err(usage(String::from(
+            "--follow can't be combined with --count, --files-without-match, --write or --diff",
+        )));
+    }
+    if config.search_zip {
+        return Err(usage(String::from("--follow can't be combined with --search-zip")));
+    }
+    match config.paths.as_slice() {
+        [path] if path == STDIN_PATH => {
+            Err(usage(String::from("--follow can't be used on standard input")))
+        }
+        [path] if Path::new(path).is_dir() => {
+            Err(usage(format!("--follow needs a file, '{path}' is a directory")))
+        }
+        [_] => Ok(()),
+        _ => Err(usage(String::from("--follow needs exactly one FILE"))),
+    }
+}
+
+// -U selects the lines a match touches, there is no line a pattern has to
+// match on its own
+fn check_multiline(config: &Config) -> Result<(), ArgsError> {
+    let line_options = config.invert_match || config.line_regexp || config.replace.is_some();
+    let other_modes = config.fuzzy.is_some() || config.boolean || config.follow;
+    if config.multiline && (line_options || other_modes) {
+        return Err(usage(String::from(
+            "--multiline can't be combined with --invert-match, --line-regexp, --replace, \
+             --fuzzy, --boolean or --follow",
+        )));
+    }
+    Ok(())
+}
+
+// With --log-format patterns are field conditions, which text matching
+// options don't apply to. -E is left alone, it can come from REGEX.
+fn check_log_format(config: &Config) -> Result<(), ArgsError> {
+    if config.log_format.is_none() {
+        if !config.select.is_empty() || config.report_invalid {
+            return Err(usage(String::from("--select and --report-invalid need --log-format")));
+        }
+        return Ok(());
+    }
+    let text_options = config.word_regexp || config.line_regexp;
+    if text_options || config.fuzzy.is_some() || config.multiline || config.replace.is_some() {
+        return Err(usage(String::from(
+            "--log-format can't be combined with --word-regexp, --line-regexp, --fuzzy, \
+             --multiline or --replace",
+        )));
+    }
+    Ok(())
+}
+
+// --extract and --field replace the printed line, and --aggregate replaces the
+// whole output with one summary of all inputs
+fn check_extract(config: &mut Config) -> Result<(), ArgsError> {
+    if config.top.is_some() && config.aggregate.is_none() {
+        config.aggregate = Some(Aggregate::Counts);
+    }
+    let extracting = config.extract.is_some() || config.field.is_some();
+    if !extracting {
+        if config.aggregate.is_some() || config.delimiter.is_some() {
+            return Err(usage(String::from(
+                "--aggregate, --top and --delimiter need --extract or --field",
+            )));
+        }
+        return Ok(());
+    }
+
+    if config.extract.is_some() && config.field.is_some() {
+        return Err(usage(String::from("--extract can't be combined with --field")));
+    }
+    let regex = config.regex && !config.boolean && config.log_format.is_none();
+    if config.extract.is_some() && !(regex && config.fuzzy.is_none()) {
+        return Err(usage(String::from(
+            "--extract needs --regex, and can't be combined with --boolean, --log-format \
+             or --fuzzy",
+        )));
+    }
+    if let Some(field) = &config.field {
+        if config.log_format.is_some() && config.delimiter.is_some() {
+            return Err(usage(String::from("--delimiter can't be combined with --log-format")));
+        }
+        if config.log_format.is_none() && !field.parse::<usize>().is_ok_and(|n| n > 0) {
+            return Err(usage(format!(
+                "invalid field '{field}', expected a number from 1 without --log-format"
+            )));
+        }
+    }
+    if config.delimiter.as_ref().is_some_and(String::is_empty) {
+        return Err(usage(String::from("--delimiter can't be empty")));
+    }
+    if config.replace.is_some() || !config.select.is_empty() || config.multiline {
+        return Err(usage(String::from(
+            "--extract and --field can't be combined with --replace, --select or --multiline",
+        )));
+    }
+
+    let list_files = config.files_with_matches || config.files_without_match;
+    let other_output = config.count || list_files || config.quiet || config.json;
+    if config.aggregate.is_some() && (other_output || config.follow) {
+        return Err(usage(String::from(
+            "--aggregate can't be combined with --count, --files-with-matches, \
+             --files-without-match, --quiet, --json or --follow",
+        )));
+    }
+    Ok(())
+}
+
+fn set_flag(config: &mut Config, long: &str, given: &str) -> Result<(), ArgsError> {
+    match long {
+        "ignore-case" => {
+            config.ignore_case = true;
+            config.smart_case = false;
+        }
+        "case-sensitive" => {
+            config.ignore_case = false;
+            config.smart_case = false;
+        }
+        "smart-case" => config.smart_case = true,
+        "regex" => config.regex = true,
+        "word-regexp" => config.word_regexp = true,
+        "line-regexp" => config.line_regexp = true,
+        "multiline" => config.multiline = true,
+        "invert-match" => config.invert_match = true,
+        "count" => config.count = true,
+        "files-with-matches" => config.files_with_matches = true,
+        "files-without-match" => config.files_without_match = true,
+        "quiet" => config.quiet = true,
+        "line-number" => config.line_number = true,
+        "byte-offset" => config.byte_offset = true,
+        "search-zip" => config.search_zip = true,
+        "json" => config.json = true,
+        "use-index" => config.use_index = true,
+        "boolean" => config.boolean = true,
+        "report-invalid" => config.report_invalid = true,
+        "follow" => config.follow = true,
+        "write" => config.write = true,
+        "diff" => config.diff = true,
+        "help" => return Err(ArgsError::Help),
+        "version" => return Err(ArgsError::Version),
+        _ => return Err(usage(format!("unknown option '{given}'"))),
+    }
+    Ok(())
+}
+
+fn set_value(
+    config: &mut Config,
+    long: &str,
+    given: &str,
+    value: Option<String>,
+) -> Result<(), Error> {
+    let value = value.ok_or_else(|| usage(format!("option '{given}' requires a value")))?;
+    let number = || {
+        value
+            .parse::<usize>()
+            .map_err(|_| usage(format!("invalid number for '{given}': '{value}'")))
+    };
+
+    match long {
+        "after-context" => config.after_context = number()?,
+        "before-context" => config.before_context = number()?,
+        "context" => {
+            config.after_context = number()?;
+            config.before_context = config.after_context;
+        }
+        "threads" => match number()? {
+            0 => return Err(usage(format!("option '{given}' needs at least 1 thread")).into()),
+            threads => config.threads = Some(threads),
+        },
+        "max-count" => config.max_count = Some(number()?),
+        "replace" => config.replace = Some(value),
+        "fuzzy" => config.fuzzy = Some(number()?),
+        "log-format" => config.log_format = Some(value.parse().map_err(usage)?),
+        "extract" => config.extract = Some(value),
+        "field" => config.field = Some(value),
+        "delimiter" => config.delimiter = Some(value),
+        "aggregate" => config.aggregate = Some(value.parse().map_err(usage)?),
+        "top" => config.top = Some(number()?),
+        "select" => {
+            let fields = value.split(',').map(str::trim).filter(|f| !f.is_empty());
+            config.select.extend(fields.map(String::from));
+        }
+        "encoding" if value == encoding::AUTO => config.encoding = None,
+        "encoding" => {
+            let encoding = encoding::for_label(&value)
+                .ok_or_else(|| Error::Encoding(format!("unknown encoding '{value}'")))?;
+            config.encoding = Some(encoding);
+        }
+        "regexp" => config.patterns.push(value),
+        "file" => {
+            let contents = fs::read_to_string(&value)
+                .map_err(|source| Error::Io { path: PathBuf::from(&value), source })?;
+            config.patterns.extend(contents.lines().map(String::from));
+        }
+        _ => config.globs.push(value),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ColorChoice, LogFormat};
+
+    fn parse_with(args: &[&str], defaults: Config) -> Result<Config, Error> {
+        let args: Vec<String> = std::iter::once("minigrep")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect();
+        parse(&args, defaults)
+    }
+
+    fn parse_args(args: &[&str]) -> Result<Config, Error> {
+        parse_with(args, Config::default())
+    }
+
+    fn args_error(args: &[&str]) -> Option<ArgsError> {
+        match parse_args(args) {
+            Err(Error::Args(err)) => Some(err),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn positional_arguments() {
+        let config = parse_args(&["query", "src", "README.md"]).unwrap();
+        assert_eq!(config.patterns, vec!["query"]);
+        assert_eq!(config.paths, vec!["src", "README.md"]);
+        assert!(config.globs.is_empty());
+        assert!(!config.ignore_case);
+    }
+
+    #[test]
+    fn missing_path_reads_stdin() {
+        let config = parse_args(&["query"]).unwrap();
+        assert_eq!(config.paths, vec!["-"]);
+    }
+
+    #[test]
+    fn short_and_long_flags() {
+        let config = parse_args(&["-n", "--count", "-v", "-z", "query", "file"]).unwrap();
+        assert!(config.line_number);
+        assert!(config.count);
+        assert!(config.invert_match);
+        assert!(config.search_zip);
+        assert!(!config.files_with_matches);
+    }
+
+    #[test]
+    fn output_modes() {
+        let config = parse_args(&["-Lq", "--max-count", "2", "query", "file"]).unwrap();
+        assert!(config.files_without_match);
+        assert!(config.quiet);
+        assert_eq!(config.max_count, Some(2));
+        assert_eq!(parse_args(&["-m0", "query"]).unwrap().max_count, Some(0));
+        assert_eq!(parse_args(&["query"]).unwrap().max_count, None);
+    }
+
+    #[test]
+    fn bundled_short_flags() {
+        let config = parse_args(&["-inlA3", "query", "file"]).unwrap();
+        assert!(config.ignore_case);
+        assert!(config.line_number);
+        assert!(config.files_with_matches);
+        assert_eq!(config.after_context, 3);
+    }
+
+    #[test]
+    fn context_values() {
+        let config = parse_args(&["-C", "2", "--after-context=4", "query", "file"]).unwrap();
+        assert_eq!(config.before_context, 2);
+        assert_eq!(config.after_context, 4);
+
+        let config = parse_args(&["--context", "1", "-B0", "query", "file"]).unwrap();
+        assert_eq!(config.before_context, 0);
+        assert_eq!(config.after_context, 1);
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let config = parse_args(&["-e", "error", "--regexp=warn", "-e-x", "log.txt"]).unwrap();
+        assert_eq!(config.patterns, vec!["error", "warn", "-x"]);
+        assert_eq!(config.paths, vec!["log.txt"]);
+        assert!(config.pattern.is_none());
+
+        let config = parse_args(&["-E", "-x", "-e", "a+", "-e", "b", "log.txt"]).unwrap();
+        let pattern = config.pattern.unwrap();
+        assert!(pattern.is_match("aaa"));
+        assert!(!pattern.is_match("aab"));
+    }
+
+    #[test]
+    fn pattern_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("patterns.txt");
+        fs::write(&path, "error\r\nTimeout\n").unwrap();
+        let file = path.to_string_lossy().into_owned();
+
+        let config = parse_args(&["-S", "-f", &file, "-e", "panic"]).unwrap();
+        assert_eq!(config.patterns, vec!["error", "Timeout", "panic"]);
+        assert_eq!(config.paths, vec!["-"]);
+        assert!(!config.ignore_case);
+
+        fs::write(&path, "").unwrap();
+        let config = parse_args(&["-f", &file, "log.txt"]).unwrap();
+        assert!(config.patterns.is_empty());
+        assert_eq!(config.paths, vec!["log.txt"]);
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(parse_args(&["-f", &file]), Err(Error::Io { .. })));
+    }
+
+    #[test]
+    fn replace_options() {
+        let config = parse_args(&["--replace", "$1", "--diff", "q", "file"]).unwrap();
+        assert_eq!(config.replace.as_deref(), Some("$1"));
+        assert!(config.diff);
+        assert!(!config.write);
+        assert!(parse_args(&["--replace=", "q"]).unwrap().replace.unwrap().is_empty());
+
+        let usage_error = |args: &[&str]| match args_error(args) {
+            Some(ArgsError::Usage(msg)) => msg,
+            err => panic!("expected a usage error, got {err:?}"),
+        };
+        assert_eq!("--write and --diff need --replace", usage_error(&["--write", "q", "file"]));
+        assert_eq!(
+            "--write and --diff can't be used on standard input",
+            usage_error(&["--replace=x", "--write", "q"])
+        );
+        assert_eq!(
+            "--replace can't be combined with --invert-match",
+            usage_error(&["--replace=x", "-v", "q"])
+        );
+    }
+
+    #[test]
+    fn fuzzy_distance() {
+        assert_eq!(parse_args(&["--fuzzy", "2", "query"]).unwrap().fuzzy, Some(2));
+        assert_eq!(parse_args(&["query"]).unwrap().fuzzy, None);
+        assert!(parse_args(&["--fuzzy=1", "-E", "query"]).is_err());
+        assert!(parse_args(&["--fuzzy=-1", "query"]).is_err());
+    }
+
+    #[test]
+    fn index_command() {
+        let args = |args: &[&str]| -> Vec<String> {
+            ["minigrep", "index"].iter().chain(args).map(|a| a.to_string()).collect()
+        };
+        assert_eq!(PathBuf::from("."), parse_index(&args(&[])).unwrap());
+        assert_eq!(PathBuf::from("src"), parse_index(&args(&["src"])).unwrap());
+        assert!(parse_index(&args(&["src", "benches"])).is_err());
+        assert!(parse_index(&args(&["poem.txt"])).is_err());
+        assert!(parse_index(&args(&["-n"])).is_err());
+        assert!(matches!(parse_index(&args(&["--help"])), Err(Error::Args(ArgsError::Help))));
+
+        assert!(parse_args(&["--use-index", "query", "."]).unwrap().use_index);
+        // Only the first argument is the subcommand
+        assert_eq!(parse_args(&["-e", "index"]).unwrap().patterns, vec!["index"]);
+    }
+
+    #[test]
+    fn follow_needs_one_file() {
+        let config = parse_args(&["--follow", "-m", "1", "error", "app.log"]).unwrap();
+        assert!(config.follow);
+        assert!(!parse_args(&["error", "app.log"]).unwrap().follow);
+        assert!(args_error(&["--follow", "error"]).is_some());
+        assert!(args_error(&["--follow", "error", "src"]).is_some());
+        assert!(args_error(&["--follow", "error", "a.log", "b.log"]).is_some());
+        assert!(args_error(&["--follow", "-c", "error", "app.log"]).is_some());
+    }
+
+    #[test]
+    fn multiline() {
+        let config = parse_args(&["-UE", r"fn \w+\(\s*$", "src"]).unwrap();
+        assert!(config.multiline);
+        assert!(config.pattern.unwrap().is_match("fn search(\n    config: &Config,"));
+        assert!(args_error(&["-U", "-v", "fn"]).is_some());
+        assert!(args_error(&["-U", "--boolean", "fn AND main"]).is_some());
+    }
+
+    #[test]
+    fn log_format() {
+        let config = parse_args(&["--log-format", "json", "--select", "level, msg", "level=error"]).unwrap();
+        assert_eq!(config.log_format, Some(LogFormat::Json));
+        assert_eq!(config.select, vec!["level", "msg"]);
+        assert_eq!(parse_args(&["level=error"]).unwrap().log_format, None);
+        assert!(args_error(&["--log-format=yaml", "level=error"]).is_some());
+        assert!(args_error(&["--select=level", "level=error"]).is_some());
+        assert!(args_error(&["--log-format=auto", "-w", "level=error"]).is_some());
+    }
+
+    #[test]
+    fn extract_and_aggregate() {
+        let config = parse_args(&["-E", "--extract", "$1", "--aggregate=histogram", "GET (\\S+)"]).unwrap();
+        assert_eq!(config.extract.as_deref(), Some("$1"));
+        assert_eq!(config.aggregate, Some(Aggregate::Histogram));
+        let config = parse_args(&["--field=3", "--delimiter", ",", "--top", "5", "GET"]).unwrap();
+        assert_eq!((config.field.as_deref(), config.top), (Some("3"), Some(5)));
+        // --top alone counts
+        assert_eq!(config.aggregate, Some(Aggregate::Counts));
+        assert!(parse_args(&["--log-format=json", "--field=.request.path", "status=500"]).is_ok());
+
+        assert!(args_error(&["--extract=$1", "GET (.*)"]).is_some());
+        assert!(args_error(&["--field=path", "GET"]).is_some());
+        assert!(args_error(&["--field=0", "GET"]).is_some());
+        assert!(args_error(&["--top=5", "GET"]).is_some());
+        assert!(args_error(&["--aggregate=sum", "--field=1", "GET"]).is_some());
+        assert!(args_error(&["--aggregate=counts", "--field=1", "-c", "GET"]).is_some());
+    }
+
+    #[test]
+    fn encoding_labels() {
+        let config = parse_args(&["--encoding", "latin1", "query"]).unwrap();
+        assert_eq!(config.encoding, Some(encoding_rs::WINDOWS_1252));
+        let config = parse_args(&["--encoding=UTF-16LE", "--encoding=auto", "query"]).unwrap();
+        assert_eq!(config.encoding, None);
+        assert!(matches!(parse_args(&["--encoding=klingon", "query"]), Err(Error::Encoding(_))));
+    }
+
+    #[test]
+    fn globs_and_threads() {
+        let config = parse_args(&["-g", "*.rs", "--glob=!target/**", "-j4", "query", "."]).unwrap();
+        assert_eq!(config.globs, vec!["*.rs", "!target/**"]);
+        assert_eq!(config.threads, Some(4));
+        assert_eq!(parse_args(&["query"]).unwrap().threads, None);
+    }
+
+    #[test]
+    fn color_choice() {
+        assert_eq!(parse_args(&["query"]).unwrap().color, ColorChoice::Auto);
+        assert_eq!(parse_args(&["--color=always", "query"]).unwrap().color, ColorChoice::Always);
+        assert_eq!(parse_args(&["--colour=never", "query"]).unwrap().color, ColorChoice::Never);
+
+        // A bare --color doesn't take the next argument as its value
+        let config = parse_args(&["--color=never", "--color", "query"]).unwrap();
+        assert_eq!(config.color, ColorChoice::Auto);
+        assert_eq!(config.patterns, vec!["query"]);
+
+        assert!(parse_args(&["--color=blue", "query"]).is_err());
+    }
+
+    #[test]
+    fn json_conflicts() {
+        assert!(parse_args(&["--json", "query"]).unwrap().json);
+        assert_eq!(
+            args_error(&["--json", "-c", "query"]),
+            Some(usage(String::from(
+                "--json can't be combined with --count, --files-with-matches or --files-without-match"
+            )))
+        );
+    }
+
+    #[test]
+    fn env_default_is_used_without_flags() {
+        let defaults = Config { ignore_case: true, ..Config::default() };
+        let config = parse_with(&["query", "file"], defaults).unwrap();
+        assert!(config.ignore_case);
+    }
+
+    #[test]
+    fn flags_override_env_default() {
+        let defaults = Config { ignore_case: true, ..Config::default() };
+        let config = parse_with(&["-s", "query", "file"], defaults).unwrap();
+        assert!(!config.ignore_case);
+    }
+
+    #[test]
+    fn last_case_flag_wins() {
+        let config = parse_args(&["-s", "-i", "query", "file"]).unwrap();
+        assert!(config.ignore_case);
+
+        let config = parse_args(&["-i", "--case-sensitive", "query", "file"]).unwrap();
+        assert!(!config.ignore_case);
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(parse_args(&["-S", "error"]).unwrap().ignore_case);
+        assert!(!parse_args(&["-S", "Error"]).unwrap().ignore_case);
+        assert!(parse_args(&["-S", "-E", r"\S+error"]).unwrap().ignore_case);
+
+        // The last case flag wins, smart case included
+        assert!(!parse_args(&["-S", "-s", "error"]).unwrap().ignore_case);
+        assert!(parse_args(&["-S", "-i", "Error"]).unwrap().ignore_case);
+        assert!(!parse_args(&["-i", "-S", "Error"]).unwrap().ignore_case);
+
+        // Smart case also overrides IGNORE_CASE
+        let defaults = Config { ignore_case: true, ..Config::default() };
+        assert!(!parse_with(&["--smart-case", "Error"], defaults).unwrap().ignore_case);
+    }
+
+    #[test]
+    fn boolean_queries() {
+        let config = parse_args(&["--boolean", "-S", "error AND NOT timeout", "app.log"]).unwrap();
+        assert_eq!("(error AND NOT timeout)", config.query.unwrap().to_string());
+        // The operators don't turn off smart case
+        assert!(config.ignore_case);
+        assert!(config.pattern.is_none());
+
+        let config = parse_args(&["--boolean", "-e", "a AND b", "-e", "c"]).unwrap();
+        assert_eq!("((a AND b) OR c)", config.query.unwrap().to_string());
+
+        let Err(Error::Pattern(msg)) = parse_args(&["--boolean", "error AND )"]) else {
+            panic!("expected a query error");
+        };
+        assert_eq!("invalid query: expected a term, found ')'\n  error AND )\n            ^", msg);
+        assert!(args_error(&["--boolean", "--replace", "x", "a AND b"]).is_some());
+    }
+
+    #[test]
+    fn regex_compiled_with_final_case_mode() {
+        let defaults = Config { regex: true, ..Config::default() };
+        let config = parse_with(&["-i", "^rust", "file"], defaults).unwrap();
+        assert!(config.pattern.unwrap().is_match("Rust:"));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let config = parse_args(&["--", "-v", "file"]).unwrap();
+        assert_eq!(config.patterns, vec!["-v"]);
+        assert!(!config.invert_match);
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert_eq!(args_error(&["--help"]), Some(ArgsError::Help));
+        assert_eq!(args_error(&["query", "-V"]), Some(ArgsError::Version));
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert_eq!(
+            args_error(&[]),
+            Some(usage(String::from("missing required argument QUERY")))
+        );
+        assert_eq!(
+            args_error(&["-y", "query", "file"]),
+            Some(usage(String::from("unknown option '-y'")))
+        );
+        assert_eq!(
+            args_error(&["--count=3", "query", "file"]),
+            Some(usage(String::from("option '--count' doesn't take a value")))
+        );
+        assert_eq!(
+            args_error(&["query", "file", "-A"]),
+            Some(usage(String::from("option '-A' requires a value")))
+        );
+        assert_eq!(
+            args_error(&["--threads", "0", "query"]),
+            Some(usage(String::from("option '--threads' needs at least 1 thread")))
+        );
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let result = parse_args(&["-E", "(", "file"]);
+        assert!(matches!(result, Err(Error::Pattern(_))));
+
+        // The error names the broken pattern, not the combined regex
+        let result = parse_args(&["-E", "-e", "ok", "-e", "(bad", "file"]);
+        let Err(Error::Pattern(message)) = result else { panic!("expected a pattern error") };
+        assert!(message.starts_with("invalid regex '(bad'"));
+    }
+}
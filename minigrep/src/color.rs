@@ -0,0 +1,69 @@
+use std::str::FromStr;
+
+// grep's default GREP_COLORS: bold red matches, magenta file names, green
+// line numbers and byte offsets, cyan separators
+pub const MATCH: &str = "\x1b[01;31m";
+pub const PATH: &str = "\x1b[35m";
+pub const LINE_NUMBER: &str = "\x1b[32m";
+pub const SEPARATOR: &str = "\x1b[36m";
+pub const RESET: &str = "\x1b[m";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Decides what `Auto` means for the output at hand. NO_COLOR only counts
+    /// when it is set to something, see <https://no-color.org>
+    pub fn resolve(self, is_terminal: bool, no_color: Option<&str>) -> ColorChoice {
+        match self {
+            ColorChoice::Auto if is_terminal && no_color.is_none_or(str::is_empty) => {
+                ColorChoice::Always
+            }
+            ColorChoice::Auto => ColorChoice::Never,
+            choice => choice,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("invalid color choice '{s}', expected auto, always or never")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_follows_terminal_and_no_color() {
+        assert_eq!(ColorChoice::Always, ColorChoice::Auto.resolve(true, None));
+        assert_eq!(ColorChoice::Always, ColorChoice::Auto.resolve(true, Some("")));
+        assert_eq!(ColorChoice::Never, ColorChoice::Auto.resolve(true, Some("1")));
+        assert_eq!(ColorChoice::Never, ColorChoice::Auto.resolve(false, None));
+    }
+
+    #[test]
+    fn explicit_choice_wins() {
+        assert_eq!(ColorChoice::Always, ColorChoice::Always.resolve(false, Some("1")));
+        assert_eq!(ColorChoice::Never, ColorChoice::Never.resolve(true, None));
+    }
+
+    #[test]
+    fn parse_choice() {
+        assert_eq!(Ok(ColorChoice::Always), "always".parse());
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+}
@@ -0,0 +1,60 @@
+use std::ops::Range;
+
+use crate::Match;
+
+/// Zero-based line ranges to print around each match. Windows that overlap or
+/// touch are merged into one group, so a line is never printed twice.
+pub fn context_ranges(
+    matches: &[Match],
+    line_count: usize,
+    before: usize,
+    after: usize,
+) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+
+    for m in matches {
+        let index = m.line_number - 1;
+        let start = index.saturating_sub(before);
+        let end = (index + after + 1).min(line_count);
+
+        match ranges.last_mut() {
+            Some(last) if start <= last.end => last.end = last.end.max(end),
+            _ => ranges.push(start..end),
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line_number: usize) -> Match<'static> {
+        Match { line_number, byte_offset: 0, line: "" }
+    }
+
+    #[test]
+    fn separate_groups() {
+        let matches = [at(2), at(9)];
+        assert_eq!(vec![0..3, 7..10], context_ranges(&matches, 10, 1, 1));
+    }
+
+    #[test]
+    fn overlapping_groups_are_merged() {
+        let matches = [at(3), at(5), at(7)];
+        assert_eq!(vec![1..8], context_ranges(&matches, 10, 1, 1));
+    }
+
+    #[test]
+    fn adjacent_groups_are_merged() {
+        let matches = [at(2), at(5)];
+        assert_eq!(vec![0..6], context_ranges(&matches, 10, 1, 1));
+    }
+
+    #[test]
+    fn clamped_to_file() {
+        let matches = [at(1), at(4)];
+        assert_eq!(vec![0..4], context_ranges(&matches, 4, 2, 3));
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+
+// Compression formats searched with -z, recognized by their magic bytes
+// rather than the file name, so compressed stdin works too
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+const MAGIC: &[(&[u8], Format)] = &[
+    (&[0x1f, 0x8b], Format::Gzip),
+    (&[0x28, 0xb5, 0x2f, 0xfd], Format::Zstd),
+    (b"BZh", Format::Bzip2),
+];
+
+pub fn detect(bytes: &[u8]) -> Option<Format> {
+    MAGIC
+        .iter()
+        .find(|(magic, _)| bytes.starts_with(magic))
+        .map(|&(_, format)| format)
+}
+
+// Wraps `reader` so it reads the decompressed data when it starts with the
+// magic bytes of a known format, and reads it as it is otherwise. Concatenated
+// streams, as left behind by `cat a.gz b.gz`, are read one after the other.
+pub fn reader<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let decoder: Box<dyn Read + 'a> = match detect(reader.fill_buf()?) {
+        Some(Format::Gzip) => Box::new(MultiGzDecoder::new(reader)),
+        Some(Format::Zstd) => Box::new(zstd::Decoder::with_buffer(reader)?),
+        Some(Format::Bzip2) => Box::new(MultiBzDecoder::new(reader)),
+        None => return Ok(Box::new(reader)),
+    };
+    Ok(Box::new(BufReader::new(decoder)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POEM: &str = include_str!("../poem.txt");
+
+    fn decompressed(bytes: &[u8]) -> String {
+        let mut contents = String::new();
+        reader(bytes).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn fixtures() {
+        let gzip = include_bytes!("../fixtures/poem.txt.gz");
+        let zstd = include_bytes!("../fixtures/poem.txt.zst");
+        let bzip2 = include_bytes!("../fixtures/poem.txt.bz2");
+
+        assert_eq!(Some(Format::Gzip), detect(gzip));
+        assert_eq!(Some(Format::Zstd), detect(zstd));
+        assert_eq!(Some(Format::Bzip2), detect(bzip2));
+
+        assert_eq!(POEM, decompressed(gzip));
+        assert_eq!(POEM, decompressed(zstd));
+        assert_eq!(POEM, decompressed(bzip2));
+    }
+
+    #[test]
+    fn concatenated_streams() {
+        let gzip = include_bytes!("../fixtures/poem.txt.gz");
+        assert_eq!(POEM.repeat(2), decompressed(&[&gzip[..], &gzip[..]].concat()));
+    }
+
+    #[test]
+    fn plain_text_is_passed_through() {
+        assert_eq!(None, detect(POEM.as_bytes()));
+        assert_eq!(POEM, decompressed(POEM.as_bytes()));
+        assert_eq!("", decompressed(b""));
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::{BufReader, Read};
+
+use encoding_rs::Encoding;
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
+
+// Label that switches back to the default: a BOM picks the encoding, and
+// without one the input is read as UTF-8
+pub const AUTO: &str = "auto";
+
+// Looks up an --encoding label, which can be any of the WHATWG names:
+// "utf-16le", "latin1", "shift_jis", ... Latin-1 is read as windows-1252, its
+// superset, like browsers do.
+pub fn for_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+// Transcodes `reader` to UTF-8. A BOM always decides the encoding, otherwise
+// `encoding` does. Without either the bytes are passed through as they are,
+// and invalid UTF-8 is replaced when the lines are searched.
+pub fn decoder<R: Read>(
+    encoding: Option<&'static Encoding>,
+    reader: R,
+) -> BufReader<DecodeReaderBytes<R, Vec<u8>>> {
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(encoding)
+        .bom_override(true)
+        .strip_bom(true)
+        .build(reader);
+    BufReader::new(decoder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(encoding: Option<&str>, bytes: &[u8]) -> String {
+        let encoding = encoding.map(|label| for_label(label).unwrap());
+        let mut contents = String::new();
+        decoder(encoding, bytes).read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn bom_decides_the_encoding() {
+        assert_eq!("hé\n", decoded(None, b"\xff\xfeh\x00\xe9\x00\n\x00"));
+        assert_eq!("hé\n", decoded(None, b"\xfe\xff\x00h\x00\xe9\x00\n"));
+        assert_eq!("hé\n", decoded(None, b"\xef\xbb\xbfh\xc3\xa9\n"));
+        // Even over an explicit encoding
+        assert_eq!("hé\n", decoded(Some("latin1"), b"\xef\xbb\xbfh\xc3\xa9\n"));
+    }
+
+    #[test]
+    fn explicit_encoding() {
+        assert_eq!("café\n", decoded(Some("latin1"), b"caf\xe9\n"));
+        assert_eq!("hé", decoded(Some("utf-16le"), b"h\x00\xe9\x00"));
+        assert!(for_label("klingon").is_none());
+    }
+
+    #[test]
+    fn without_bom_bytes_pass_through() {
+        let mut bytes = Vec::new();
+        decoder(None, &b"caf\xe9\n"[..]).read_to_end(&mut bytes).unwrap();
+        assert_eq!(b"caf\xe9\n", &bytes[..]);
+    }
+}
@@ -0,0 +1,108 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cli::ArgsError;
+
+/// Everything that can stop a search. `main` exits with 2 for all of these,
+/// except `--help` and `--version`, which end parsing early but succeed.
+#[derive(Debug)]
+pub enum Error {
+    /// The command line couldn't be used, or asked for help or the version
+    Args(ArgsError),
+    /// Reading an input, a pattern file or a directory failed
+    Io { path: PathBuf, source: io::Error },
+    /// A pattern, or a glob, that doesn't compile
+    Pattern(String),
+    /// An --encoding that isn't known
+    Encoding(String),
+    /// Writing the results failed, for example because the pipe was closed
+    Output(io::Error),
+    /// This many inputs couldn't be searched, but the others were. The error
+    /// of each one was printed on stderr when it happened.
+    Incomplete(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Args(err) => write!(f, "{err}"),
+            Error::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Error::Pattern(msg) | Error::Encoding(msg) => write!(f, "{msg}"),
+            Error::Output(source) => write!(f, "can't write output: {source}"),
+            Error::Incomplete(failed) => write!(f, "{failed} input(s) couldn't be searched"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Args(err) => Some(err),
+            Error::Io { source, .. } | Error::Output(source) => Some(source),
+            Error::Pattern(_) | Error::Encoding(_) | Error::Incomplete(_) => None,
+        }
+    }
+}
+
+impl From<ArgsError> for Error {
+    fn from(err: ArgsError) -> Error {
+        Error::Args(err)
+    }
+}
+
+// An IO error without a path can only come from writing the output
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Output(err)
+    }
+}
+
+// The inputs that couldn't be searched. Like in grep an input that can't be
+// read is reported on stderr and the other inputs are still searched, and the
+// search fails at the end. Shared by the threads of a parallel search.
+#[derive(Default)]
+pub struct Failures(AtomicUsize);
+
+impl Failures {
+    // A failed input counts as nothing found in it. Failing to write the
+    // output isn't about one input and stops the search.
+    pub fn skip<T: Default>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        match result {
+            Err(err @ Error::Output(_)) => Err(err),
+            Err(err) => {
+                eprintln!("minigrep: {err}");
+                self.0.fetch_add(1, Ordering::Relaxed);
+                Ok(T::default())
+            }
+            result => result,
+        }
+    }
+
+    // `matched` when every input could be searched
+    pub fn finish(self, matched: bool) -> Result<bool, Error> {
+        match self.0.into_inner() {
+            0 => Ok(matched),
+            failed => Err(Error::Incomplete(failed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_name_the_path() {
+        let err = Error::Io {
+            path: PathBuf::from("poem.txt"),
+            source: io::Error::new(io::ErrorKind::NotFound, "No such file or directory"),
+        };
+        assert_eq!("poem.txt: No such file or directory", err.to_string());
+
+        let err = Error::from(ArgsError::Usage(String::from("unknown option '-y'")));
+        assert_eq!("unknown option '-y'", err.to_string());
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::Failures;
+use crate::files::Input;
+use crate::json::Submatch;
+use crate::matcher::Matcher;
+use crate::printer;
+use crate::sink::Sink;
+use crate::structured::Record;
+use crate::{Config, Error, Match, Searcher};
+
+// --extract and --field: the part of a selected line that is printed instead
+// of the line, or counted with --aggregate. None when the line doesn't have
+// it, like a group that didn't take part in the match or a field past the end.
+pub fn value(config: &Config, matcher: &Matcher, line: &str) -> Option<String> {
+    if let Some(template) = &config.extract {
+        return matcher.expand(line, template);
+    }
+    let field = config.field.as_deref()?;
+    if let Some(format) = config.log_format {
+        let record = Record::parse(format, line).ok()?;
+        return record.get(field).map(|value| value.into_owned());
+    }
+
+    // Checked to be a number from 1 when parsing the arguments
+    let index = field.parse::<usize>().ok()?.checked_sub(1)?;
+    let value = match config.delimiter.as_deref() {
+        Some(delimiter) => line.split(delimiter).nth(index),
+        None => line.split_whitespace().nth(index),
+    };
+    value.map(String::from)
+}
+
+/// How --aggregate prints the extracted values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    /// Each distinct value with how often it was seen, like `sort | uniq -c`
+    Counts,
+    /// The same as a bar chart
+    Histogram,
+}
+
+impl FromStr for Aggregate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Aggregate, String> {
+        match s {
+            "counts" => Ok(Aggregate::Counts),
+            "histogram" => Ok(Aggregate::Histogram),
+            _ => Err(format!("invalid aggregate '{s}', expected counts or histogram")),
+        }
+    }
+}
+
+// Width of the longest bar of a histogram
+const BAR_WIDTH: usize = 40;
+
+// Searches all inputs, counts the extracted value of every selected line, and
+// prints the counts from the most to the least common. Returns whether any
+// line was selected.
+pub fn aggregate<W: Write>(
+    searcher: &Searcher,
+    aggregate: Aggregate,
+    inputs: &[Input],
+    failures: &Failures,
+    out: &mut W,
+) -> Result<bool, Error> {
+    let config = searcher.config();
+    let mut counter = Counter {
+        counts: HashMap::new(),
+        path: String::new(),
+        report_invalid: config.report_invalid,
+    };
+    let mut matched = false;
+    for input in inputs {
+        matched |= failures.skip(searcher.search_input(input, &mut counter))? > 0;
+    }
+
+    // Most common first, and equally common ones in order
+    let mut counts: Vec<(String, usize)> = counter.counts.into_iter().collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    counts.truncate(config.top.unwrap_or(usize::MAX));
+
+    let max = counts.first().map_or(0, |&(_, count)| count);
+    let count_width = max.to_string().len();
+    let value_width = counts.iter().map(|(value, _)| value.chars().count()).max().unwrap_or(0);
+    for (value, count) in &counts {
+        match aggregate {
+            Aggregate::Counts => writeln!(out, "{count:>count_width$} {value}")?,
+            Aggregate::Histogram => {
+                let bar = "#".repeat((count * BAR_WIDTH).div_ceil(max));
+                writeln!(out, "{value:<value_width$}  {count:>count_width$} {bar}")?;
+            }
+        }
+    }
+    Ok(matched)
+}
+
+// With --extract or --field the search passes the value of each selected line
+// as the line, which is all that is counted. Lines of binary input aren't.
+struct Counter {
+    counts: HashMap<String, usize>,
+    // The input being searched, for --report-invalid
+    path: String,
+    report_invalid: bool,
+}
+
+impl Sink for Counter {
+    fn begin(&mut self, path: &Path) {
+        self.path = path.display().to_string();
+    }
+
+    fn matched(&mut self, line: &Match, _: &[Submatch]) -> io::Result<()> {
+        *self.counts.entry(line.line.to_string()).or_default() += 1;
+        Ok(())
+    }
+
+    fn invalid(&mut self, line_number: usize, reason: &str) {
+        if self.report_invalid {
+            printer::report_invalid(&self.path, line_number, reason);
+        }
+    }
+
+    fn wants_submatches(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn regex_config(pattern: &str, config: Config) -> Config {
+        let patterns = vec![String::from(pattern)];
+        let pattern = Config::compile_patterns(&patterns, false, false, false).unwrap();
+        Config { patterns, regex: true, pattern: Some(pattern), ..config }
+    }
+
+    fn extracted(config: &Config, line: &str) -> Option<String> {
+        value(config, &Matcher::new(config).unwrap(), line)
+    }
+
+    const LINE: &str = "10.0.0.7 - GET /api/users 200 31ms";
+
+    #[test]
+    fn capture_groups_and_fields() {
+        let config = regex_config(r"(?P<method>GET|POST) (\S+)", Config::default());
+        let config = Config { extract: Some(String::from("$method $2")), ..config };
+        assert_eq!(Some(String::from("GET /api/users")), extracted(&config, LINE));
+        assert_eq!(None, extracted(&config, "no request here"));
+
+        let field = |field: &str, delimiter: Option<&str>| Config {
+            patterns: vec![String::from("GET")],
+            field: Some(field.to_string()),
+            delimiter: delimiter.map(String::from),
+            ..Config::default()
+        };
+        assert_eq!(Some(String::from("200")), extracted(&field("5", None), LINE));
+        assert_eq!(Some(String::from("0")), extracted(&field("2", Some(".")), LINE));
+        assert_eq!(None, extracted(&field("9", None), LINE));
+
+        let config = Config {
+            patterns: vec![String::from("status=500")],
+            log_format: Some(crate::LogFormat::Auto),
+            field: Some(String::from(".request.path")),
+            ..Config::default()
+        };
+        let line = r#"{"status":500,"request":{"path":"/api/orders"}}"#;
+        assert_eq!(Some(String::from("/api/orders")), extracted(&config, line));
+    }
+
+    #[test]
+    fn counts_and_histogram() {
+        let contents = "\
+GET /a 200
+GET /b 500
+POST /a 200
+GET /c 200
+GET /a 404
+";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        fs::write(&path, contents).unwrap();
+        let inputs = [Input::File { path: path.clone() }];
+        let searcher = |top| {
+            let config = regex_config("GET", Config::default());
+            Searcher::new(Config { field: Some(String::from("3")), top, ..config }).unwrap()
+        };
+        let failures = Failures::default();
+
+        let mut out = Vec::new();
+        assert!(aggregate(&searcher(None), Aggregate::Counts, &inputs, &failures, &mut out).unwrap());
+        assert_eq!("2 200\n1 404\n1 500\n", String::from_utf8(out).unwrap());
+
+        let mut out = Vec::new();
+        aggregate(&searcher(Some(2)), Aggregate::Histogram, &inputs, &failures, &mut out).unwrap();
+        let bars = format!("200  2 {}\n404  1 {}\n", "#".repeat(40), "#".repeat(20));
+        assert_eq!(bars, String::from_utf8(out).unwrap());
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+
+use crate::error::Failures;
+use crate::{Error, STDIN_PATH};
+
+pub enum Input {
+    Stdin,
+    File { path: PathBuf },
+}
+
+// Expands the paths given on the command line into the inputs to search, in
+// the order they will be printed. Parts of a directory that can't be read are
+// skipped with `failures`.
+pub fn collect_inputs(
+    paths: &[String],
+    globs: &[String],
+    failures: &Failures,
+) -> Result<Vec<Input>, Error> {
+    let mut inputs = Vec::new();
+
+    for path in paths {
+        if path == STDIN_PATH {
+            inputs.push(Input::Stdin);
+        } else if Path::new(path).is_dir() {
+            let skip = |err| failures.skip(Err(err));
+            for file in collect_files(Path::new(path), globs, skip)? {
+                inputs.push(Input::File { path: file });
+            }
+        } else {
+            inputs.push(Input::File { path: PathBuf::from(path) });
+        }
+    }
+
+    Ok(inputs)
+}
+
+// Same heuristic as grep: a NUL byte near the start means binary data
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+// An entry of `root` that can't be read is passed to `walk_error`, and the
+// walk goes on when that returns Ok
+pub fn collect_files(
+    root: &Path,
+    globs: &[String],
+    mut walk_error: impl FnMut(Error) -> Result<(), Error>,
+) -> Result<Vec<PathBuf>, Error> {
+    let invalid_glob = |e: ignore::Error| Error::Pattern(e.to_string());
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in globs {
+        overrides.add(glob).map_err(invalid_glob)?;
+    }
+
+    let walker = WalkBuilder::new(root)
+        .overrides(overrides.build().map_err(invalid_glob)?)
+        .require_git(false)
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                walk_error(with_path(root.to_path_buf(), err))?;
+                continue;
+            }
+        };
+        if entry.file_type().is_some_and(|t| t.is_file()) {
+            files.push(entry.into_path());
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+// Walk errors are mostly wrapped IO errors that say which path they are about
+fn with_path(path: PathBuf, err: ignore::Error) -> Error {
+    match err {
+        ignore::Error::WithPath { path, err } => with_path(path, *err),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            with_path(path, *err)
+        }
+        ignore::Error::Io(source) => Error::Io { path, source },
+        err => Error::Io { path, source: io::Error::other(err) },
+    }
+}
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(BINARY_SNIFF_LEN).any(|&b| b == 0)
+}
+
+// Writes `contents` to a temporary file next to `path` and renames it over
+// `path`, so the file is never left half written. An existing file keeps its
+// permissions.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{name}.minigrep-{}", process::id()));
+    let permissions = match fs::metadata(path) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    let result = write_file(&temp, contents, permissions).and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_file(
+    path: &Path,
+    contents: &[u8],
+    permissions: Option<fs::Permissions>,
+) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relative(root: &Path, files: Vec<PathBuf>) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| f.strip_prefix(root).unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn walk_with_globs_and_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::write(root.join("src/lib.rs"), "lib").unwrap();
+        fs::write(root.join("src/notes.txt"), "notes").unwrap();
+        fs::write(root.join("target/build.rs"), "build").unwrap();
+        fs::write(root.join("logs/app.rs"), "log").unwrap();
+        fs::write(root.join(".gitignore"), "logs/\n").unwrap();
+
+        let all = collect_files(&root, &[], Err).unwrap();
+        assert_eq!(
+            vec!["src/lib.rs", "src/notes.txt", "target/build.rs"],
+            relative(&root, all)
+        );
+
+        let globs = [String::from("*.rs"), String::from("!target/**")];
+        let filtered = collect_files(&root, &globs, Err).unwrap();
+        assert_eq!(vec!["src/lib.rs"], relative(&root, filtered));
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(!is_binary(b"plain text\n"));
+        assert!(is_binary(b"ELF\0\0\x01"));
+    }
+}
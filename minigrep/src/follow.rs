@@ -0,0 +1,139 @@
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// How often --follow checks for new lines once it has read everything
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Reads what is appended to a file, like `tail -f`: at the end of the file it
+// waits for more instead of returning 0, so reading never ends. The file is
+// watched by name, which survives log rotation: when it is truncated it is
+// read again from the start, and when it is replaced (moved away and created
+// again) the new file is opened, once it exists.
+pub struct Follow {
+    path: PathBuf,
+    file: File,
+    position: u64,
+    poll: Duration,
+}
+
+impl Follow {
+    // Starts at the current end of the file
+    pub fn open(path: &Path, poll: Duration) -> io::Result<Follow> {
+        let mut file = File::open(path)?;
+        let position = file.seek(SeekFrom::End(0))?;
+        Ok(Follow { path: path.to_path_buf(), file, position, poll })
+    }
+
+    // Switches to the start of the file when it was rotated, and returns
+    // whether it did
+    fn reopen_if_rotated(&mut self) -> io::Result<bool> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // Moved away, and the new file isn't there yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        if !same_file(&metadata, &self.file.metadata()?) {
+            self.file = File::open(&self.path)?;
+        } else if metadata.len() < self.position {
+            self.file.seek(SeekFrom::Start(0))?;
+        } else {
+            return Ok(false);
+        }
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl Read for Follow {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let read = self.file.read(buf)?;
+            if read > 0 {
+                self.position += read as u64;
+                return Ok(read);
+            }
+            // Everything before the rotation has been read by now
+            if !self.reopen_if_rotated()? {
+                thread::sleep(self.poll);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+// Without inode numbers a replaced file looks like the same one, which is
+// still noticed when it is smaller than what was read
+#[cfg(not(unix))]
+fn same_file(_: &Metadata, _: &Metadata) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn survives_truncation_and_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "before following\n").unwrap();
+
+        let mut reader =
+            BufReader::new(Follow::open(&path, Duration::from_millis(5)).unwrap());
+        let mut next_line = || {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            line
+        };
+        // The sleeps only make it likely that the reader sees the states in
+        // between. Each rotation waits for the line before it to be read, or
+        // the reader could miss that line.
+        let pause = || thread::sleep(Duration::from_millis(20));
+        let (step, steps) = mpsc::channel();
+
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                append(&path, "first, in ");
+                pause();
+                append(&path, "two writes\n");
+                steps.recv().unwrap();
+                // copytruncate style rotation
+                fs::write(&path, "truncated\n").unwrap();
+                steps.recv().unwrap();
+                // Moved away and created again
+                fs::rename(&path, path.with_extension("log.1")).unwrap();
+                pause();
+                fs::write(&path, "new file\n").unwrap();
+            })
+        };
+
+        assert_eq!("first, in two writes\n", next_line());
+        step.send(()).unwrap();
+        assert_eq!("truncated\n", next_line());
+        step.send(()).unwrap();
+        assert_eq!("new file\n", next_line());
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+use std::ops::Range;
+
+use aho_corasick::AhoCorasick;
+
+// Approximate substring search: finds the parts of a text that are within
+// `max_distance` edits (Levenshtein: insert, delete or substitute a character)
+// of the pattern.
+//
+// This is Sellers' algorithm, the edit distance table of the pattern against
+// the text where an alignment may start anywhere in the text, one text
+// character per column. That is O(pattern * text), so lines are first
+// filtered by the pigeonhole principle: when the pattern is cut into
+// `max_distance + 1` pieces, k edits can touch at most k of them, so a line
+// within the distance contains at least one piece exactly.
+pub struct Fuzzy {
+    pattern: Vec<char>,
+    max_distance: usize,
+    // None when the pattern is too short to cut into pieces
+    pieces: Option<AhoCorasick>,
+}
+
+impl Fuzzy {
+    pub fn new(pattern: &str, max_distance: usize) -> Fuzzy {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let pieces = if pattern.len() > max_distance {
+            let count = max_distance + 1;
+            let pieces = (0..count).map(|n| {
+                let piece = &pattern[n * pattern.len() / count..(n + 1) * pattern.len() / count];
+                piece.iter().collect::<String>()
+            });
+            AhoCorasick::new(pieces).ok()
+        } else {
+            None
+        };
+        Fuzzy { pattern, max_distance, pieces }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        // Deleting the whole pattern is within the distance, so even an empty
+        // line matches
+        self.pattern.len() <= self.max_distance || !self.find_iter(text).is_empty()
+    }
+
+    // The non-overlapping matches in `text` as byte ranges with their
+    // distance. Where several alignments overlap the closest one wins, and of
+    // equally close ones the longest.
+    pub fn find_iter(&self, text: &str) -> Vec<(Range<usize>, usize)> {
+        if self.pieces.as_ref().is_some_and(|pieces| !pieces.is_match(text)) {
+            return Vec::new();
+        }
+
+        let m = self.pattern.len();
+        // One column of the table: the distance of the best alignment of each
+        // pattern prefix ending at the current text position, and where in the
+        // text that alignment starts
+        let mut distance: Vec<usize> = (0..=m).collect();
+        let mut start = vec![0; m + 1];
+
+        let mut found: Vec<(Range<usize>, usize)> = Vec::new();
+        // The best match of the current run of positions within the distance
+        let mut best: Option<(Range<usize>, usize)> = None;
+
+        for (offset, c) in text.char_indices() {
+            let end = offset + c.len_utf8();
+            let (mut diagonal, mut diagonal_start) = (distance[0], start[0]);
+            // The empty pattern prefix aligns anywhere at no cost
+            start[0] = end;
+
+            for i in 1..=m {
+                let (left, left_start) = (distance[i], start[i]);
+                let substitute = diagonal + usize::from(self.pattern[i - 1] != c);
+                let skip_pattern = distance[i - 1] + 1;
+                let skip_text = left + 1;
+
+                (distance[i], start[i]) = if substitute <= skip_pattern && substitute <= skip_text {
+                    (substitute, diagonal_start)
+                } else if skip_pattern <= skip_text {
+                    (skip_pattern, start[i - 1])
+                } else {
+                    (skip_text, left_start)
+                };
+                (diagonal, diagonal_start) = (left, left_start);
+            }
+
+            if distance[m] <= self.max_distance {
+                let candidate = (start[m]..end, distance[m]);
+                if best.as_ref().is_none_or(|(_, d)| candidate.1 <= *d) {
+                    best = Some(candidate);
+                }
+            } else if let Some(done) = best.take() {
+                found.push(done);
+            }
+        }
+        found.extend(best);
+
+        // Alignments of neighbouring runs can still overlap
+        non_overlapping(found)
+    }
+}
+
+// Drops the matches that overlap an earlier one, `matches` are in order of
+// where they start
+pub fn non_overlapping(matches: Vec<(Range<usize>, usize)>) -> Vec<(Range<usize>, usize)> {
+    let mut kept: Vec<(Range<usize>, usize)> = Vec::new();
+    for (span, distance) in matches {
+        if kept.last().is_none_or(|(last, _)| span.start >= last.end) {
+            kept.push((span, distance));
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(pattern: &str, max_distance: usize, text: &'a str) -> Vec<(&'a str, usize)> {
+        Fuzzy::new(pattern, max_distance)
+            .find_iter(text)
+            .into_iter()
+            .map(|(span, distance)| (&text[span], distance))
+            .collect()
+    }
+
+    #[test]
+    fn exact_and_typos() {
+        assert_eq!(vec![("connection", 0)], find("connection", 2, "lost connection to db"));
+        assert_eq!(vec![("conection", 1)], find("connection", 2, "lost conection to db"));
+        assert_eq!(vec![("connectoin", 2)], find("connection", 2, "lost connectoin to db"));
+        assert!(find("connection", 1, "lost connectoin to db").is_empty());
+        assert!(find("connection", 2, "lost cnonetcoin to db").is_empty());
+    }
+
+    #[test]
+    fn closest_alignment_wins() {
+        // "rus" and "rusty" are within 1 as well
+        assert_eq!(vec![("rust", 0)], find("rust", 1, "rusty"));
+        assert_eq!(vec![("timeout", 0), ("tmeout", 1)], find("timeout", 1, "timeout! tmeout"));
+    }
+
+    #[test]
+    fn unicode_characters_are_single_edits() {
+        assert_eq!(vec![("Straße", 2)], find("Strasse", 2, "Die Straße"));
+        assert_eq!(vec![("naïve", 1)], find("naive", 1, "a naïve approach"));
+    }
+
+    #[test]
+    fn short_patterns_match_everything() {
+        let fuzzy = Fuzzy::new("ab", 2);
+        assert!(fuzzy.is_match(""));
+        assert!(fuzzy.is_match("xyz"));
+        assert!(!Fuzzy::new("abc", 2).is_match(""));
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::str;
+use std::time::UNIX_EPOCH;
+
+use regex_syntax::hir::literal::Extractor;
+use regex_syntax::ParserBuilder;
+
+use crate::files::{self, Input};
+use crate::{case, encoding, Config, Error};
+
+// A trigram index of a directory: for every file, the set of 3 byte sequences
+// in its case folded lines. A line can only contain a literal when the file
+// has every trigram of the folded literal, so --use-index skips the files that
+// lack them without reading them. Folding works character by character, so a
+// literal that is in a line is still in it after folding both, and one index
+// serves case sensitive and insensitive searches alike.
+//
+// Each file is stored with its modification time and size. `minigrep index`
+// only rereads the files where those changed, and a search reads files that
+// changed since they were indexed, or that are new, as it would without one.
+
+/// Where the index of a directory is kept, hidden files aren't searched
+pub const INDEX_FILE: &str = ".minigrep-index";
+
+const MAGIC: &[u8] = b"minigrep index 1\n";
+
+struct Entry {
+    // Relative to the indexed directory
+    path: String,
+    // Nanoseconds since the Unix epoch
+    modified: u64,
+    size: u64,
+    // Sorted, each one is the 3 bytes in big-endian order
+    trigrams: Vec<u32>,
+}
+
+#[derive(Default)]
+struct Index {
+    // Sorted by path
+    entries: Vec<Entry>,
+}
+
+/// What `minigrep index` did
+#[derive(Debug, PartialEq, Eq)]
+pub struct IndexStats {
+    /// Files in the index
+    pub files: usize,
+    /// Files that were new or changed, and were read
+    pub read: usize,
+    /// Files that were indexed before and are gone
+    pub removed: usize,
+}
+
+/// Builds the index of `root`, or brings an existing one up to date. An index
+/// that can't be read is built again from scratch.
+pub fn update_index(root: &Path) -> Result<IndexStats, Error> {
+    let index_path = root.join(INDEX_FILE);
+    let mut old: HashMap<String, Entry> = Index::load(&index_path)
+        .unwrap_or_default()
+        .entries
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+
+    let mut entries = Vec::new();
+    let mut read = 0;
+    // The index is only written when all of `root` could be read
+    for path in files::collect_files(root, &[], Err)? {
+        // Paths that aren't UTF-8 are left out, and always searched
+        let Some(relative) = path.strip_prefix(root).ok().and_then(Path::to_str) else {
+            continue;
+        };
+        let with_path = |source| Error::Io { path: path.clone(), source };
+        let (modified, size) = stamp(&fs::metadata(&path).map_err(with_path)?);
+
+        let entry = match old.remove(relative) {
+            Some(entry) if (entry.modified, entry.size) == (modified, size) => entry,
+            _ => {
+                read += 1;
+                let trigrams = file_trigrams(&path).map_err(with_path)?;
+                Entry { path: relative.to_string(), modified, size, trigrams }
+            }
+        };
+        entries.push(entry);
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let index = Index { entries };
+    files::write_atomically(&index_path, &index.to_bytes())
+        .map_err(|source| Error::Io { path: index_path, source })?;
+
+    Ok(IndexStats { files: index.entries.len(), read, removed: old.len() })
+}
+
+// --use-index: drops the files of indexed directories that can't have a
+// matching line. Searches the index can't help with, like -v or --fuzzy, get
+// `inputs` back as they are.
+pub fn filter_inputs(config: &Config, mut inputs: Vec<Input>) -> Result<Vec<Input>, Error> {
+    let Some(query) = Query::new(config) else {
+        return Ok(inputs);
+    };
+
+    let mut indexes = Vec::new();
+    for root in config.paths.iter().map(Path::new).filter(|path| path.is_dir()) {
+        let path = root.join(INDEX_FILE);
+        match Index::load(&path) {
+            Ok(index) => indexes.push((root, index)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(source) => return Err(Error::Io { path, source }),
+        }
+    }
+
+    inputs.retain(|input| {
+        let Input::File { path } = input else {
+            return true;
+        };
+        indexes.iter().find_map(|(root, index)| {
+            let relative = path.strip_prefix(root).ok()?;
+            Some(index.may_match(path, relative, &query))
+        })
+        .unwrap_or(true)
+    });
+    Ok(inputs)
+}
+
+impl Index {
+    fn load(path: &Path) -> io::Result<Index> {
+        Index::from_bytes(&fs::read(path)?)
+    }
+
+    // Whether the file at `path` can have a line that matches
+    fn may_match(&self, path: &Path, relative: &Path, query: &Query) -> bool {
+        let found = relative
+            .to_str()
+            .and_then(|r| self.entries.binary_search_by(|e| e.path.as_str().cmp(r)).ok());
+        let Some(entry) = found.map(|i| &self.entries[i]) else {
+            return true;
+        };
+
+        match fs::metadata(path) {
+            Ok(metadata) if stamp(&metadata) == (entry.modified, entry.size) => {
+                query.matches(&entry.trigrams)
+            }
+            // Changed since it was indexed, or the search reports the error
+            _ => true,
+        }
+    }
+
+    // The magic line, then varints: the number of files, and for each file its
+    // path, modification time, size and trigrams, which are delta encoded
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        put_varint(&mut out, self.entries.len() as u64);
+        for entry in &self.entries {
+            put_varint(&mut out, entry.path.len() as u64);
+            out.extend_from_slice(entry.path.as_bytes());
+            put_varint(&mut out, entry.modified);
+            put_varint(&mut out, entry.size);
+            put_varint(&mut out, entry.trigrams.len() as u64);
+            let mut previous = 0;
+            for &trigram in &entry.trigrams {
+                put_varint(&mut out, u64::from(trigram - previous));
+                previous = trigram;
+            }
+        }
+        out
+    }
+
+    fn from_bytes(bytes: &[u8]) -> io::Result<Index> {
+        let mut reader = Reader { bytes: bytes.strip_prefix(MAGIC).ok_or_else(corrupt)? };
+        let mut entries = Vec::new();
+        for _ in 0..reader.varint()? {
+            let len = reader.len()?;
+            let path = str::from_utf8(reader.take(len)?).map_err(|_| corrupt())?.to_string();
+            let modified = reader.varint()?;
+            let size = reader.varint()?;
+
+            let mut trigrams = Vec::new();
+            let mut previous: u32 = 0;
+            for _ in 0..reader.varint()? {
+                let delta = u32::try_from(reader.varint()?).map_err(|_| corrupt())?;
+                previous = previous.checked_add(delta).ok_or_else(corrupt)?;
+                trigrams.push(previous);
+            }
+            entries.push(Entry { path, modified, size, trigrams });
+        }
+        Ok(Index { entries })
+    }
+}
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "not a valid index, run `minigrep index` again")
+}
+
+fn put_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut n = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.bytes.split_first().ok_or_else(corrupt)?;
+            self.bytes = rest;
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(n);
+            }
+        }
+        Err(corrupt())
+    }
+
+    fn len(&mut self) -> io::Result<usize> {
+        usize::try_from(self.varint()?).map_err(|_| corrupt())
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.bytes.len() {
+            return Err(corrupt());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+}
+
+fn stamp(metadata: &fs::Metadata) -> (u64, u64) {
+    let modified = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+    (modified.map_or(0, |since| since.as_nanos() as u64), metadata.len())
+}
+
+fn trigrams(text: &str) -> impl Iterator<Item = u32> + '_ {
+    text.as_bytes().windows(3).map(|w| u32::from_be_bytes([0, w[0], w[1], w[2]]))
+}
+
+// The trigrams of the lines a search sees in the file: decoded as its BOM
+// says, with invalid UTF-8 replaced
+fn file_trigrams(path: &Path) -> io::Result<Vec<u32>> {
+    let mut reader = encoding::decoder(None, BufReader::new(File::open(path)?));
+    let mut found = HashSet::new();
+    let mut line = Vec::new();
+
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        found.extend(trigrams(&case::fold(&String::from_utf8_lossy(&line))));
+        line.clear();
+    }
+
+    let mut found: Vec<u32> = found.into_iter().collect();
+    found.sort_unstable();
+    Ok(found)
+}
+
+// The trigrams a file needs to have a matching line: all of those of at least
+// one of the alternatives
+struct Query {
+    alternatives: Vec<Vec<u32>>,
+}
+
+impl Query {
+    // None when the index can't tell which files may have a selected line
+    fn new(config: &Config) -> Option<Query> {
+        // -v, -c and -L select or print files without matches too, -z and
+        // --encoding search other text than was indexed, -U matches can span
+        // the lines trigrams are taken from, and field conditions aren't text
+        if config.invert_match
+            || config.boolean
+            || config.multiline
+            || config.log_format.is_some()
+            || config.count
+            || config.files_without_match
+            || config.fuzzy.is_some()
+            || config.search_zip
+            || config.encoding.is_some()
+        {
+            return None;
+        }
+
+        let literals = match &config.pattern {
+            Some(pattern) => required_literals(pattern.as_str())?,
+            None => config.patterns.clone(),
+        };
+        let alternatives = literals
+            .iter()
+            .map(|literal| {
+                let mut needed: Vec<u32> = trigrams(&case::fold(literal)).collect();
+                needed.sort_unstable();
+                needed.dedup();
+                needed
+            })
+            .collect();
+        Some(Query { alternatives })
+    }
+
+    fn matches(&self, trigrams: &[u32]) -> bool {
+        self.alternatives
+            .iter()
+            .any(|needed| needed.iter().all(|t| trigrams.binary_search(t).is_ok()))
+    }
+}
+
+// Literals one of which starts every match of the regex, None when there are
+// too many or they can't be known. Case insensitivity doesn't matter, the
+// literals are folded like the index is, and what the simple folding of -E
+// lets a literal match folds to the same text.
+fn required_literals(pattern: &str) -> Option<Vec<String>> {
+    let hir = ParserBuilder::new().build().parse(pattern).ok()?;
+    let prefixes = Extractor::new().extract(&hir);
+    let literals = prefixes.literals()?.iter().map(|literal| {
+        // A literal can end in the middle of a character, which is dropped
+        let bytes = literal.as_bytes();
+        let valid = str::from_utf8(bytes).map_or_else(|e| e.valid_up_to(), |s| s.len());
+        String::from_utf8_lossy(&bytes[..valid]).into_owned()
+    });
+    Some(literals.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Failures;
+
+    fn query(config: Config) -> Option<Query> {
+        let mut config = config;
+        if config.regex {
+            config.pattern = Some(Config::compile_patterns(&config.patterns, false, false, false).unwrap());
+        }
+        Query::new(&config)
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn sorted(text: &str) -> Vec<u32> {
+        let mut found: Vec<u32> = trigrams(&case::fold(text)).collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    #[test]
+    fn literal_queries() {
+        let q = query(Config { patterns: patterns(&["Nobody"]), ..Config::default() }).unwrap();
+        assert!(q.matches(&sorted("I'm NOBODY! Who are you?")));
+        assert!(!q.matches(&sorted("I'm somebody")));
+
+        // Folding keeps "sse" in "Straße"
+        let q = query(Config { patterns: patterns(&["STRASSE"]), ..Config::default() }).unwrap();
+        assert!(q.matches(&sorted("Hauptstraße 1")));
+
+        // Patterns shorter than a trigram match every file
+        let q = query(Config { patterns: patterns(&["no", "frog"]), ..Config::default() });
+        assert!(q.unwrap().matches(&sorted("")));
+
+        let fuzzy = Config { patterns: patterns(&["frog"]), fuzzy: Some(1), ..Config::default() };
+        assert!(query(fuzzy).is_none());
+        let invert = Config { patterns: patterns(&["frog"]), invert_match: true, ..Config::default() };
+        assert!(query(invert).is_none());
+    }
+
+    #[test]
+    fn regex_queries() {
+        let config = Config { patterns: patterns(&["(bog|frog)s?"]), regex: true, ..Config::default() };
+        let q = query(config).unwrap();
+        assert!(q.matches(&sorted("an admiring bog")));
+        assert!(q.matches(&sorted("like a frog")));
+        assert!(!q.matches(&sorted("how dreary")));
+
+        // Too many possible prefixes
+        let config = Config { patterns: patterns(&[r"\w+ody"]), regex: true, ..Config::default() };
+        assert!(query(config).is_none());
+    }
+
+    #[test]
+    fn round_trip() {
+        let index = Index {
+            entries: vec![Entry {
+                path: String::from("poem.txt"),
+                modified: 1_700_000_000_000_000_000,
+                size: 120,
+                trigrams: sorted("Then there's a pair of us"),
+            }],
+        };
+        let bytes = index.to_bytes();
+        let read = Index::from_bytes(&bytes).unwrap();
+        assert_eq!(1, read.entries.len());
+        assert_eq!("poem.txt", read.entries[0].path);
+        assert_eq!(1_700_000_000_000_000_000, read.entries[0].modified);
+        assert_eq!(index.entries[0].trigrams, read.entries[0].trigrams);
+
+        assert!(Index::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Index::from_bytes(b"poem.txt").is_err());
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        fs::create_dir_all(root.join("poems")).unwrap();
+        fs::write(root.join("poems/nobody.txt"), "I'm nobody! Who are you?\n").unwrap();
+        fs::write(root.join("poems/frog.txt"), "How public, like a frog\n").unwrap();
+        fs::write(root.join("notes.txt"), "nothing to see\n").unwrap();
+
+        let stats = update_index(&root).unwrap();
+        assert_eq!(IndexStats { files: 3, read: 3, removed: 0 }, stats);
+        assert_eq!(IndexStats { files: 3, read: 0, removed: 0 }, update_index(&root).unwrap());
+
+        let config = Config {
+            patterns: patterns(&["frog"]),
+            paths: vec![root.to_string_lossy().into_owned()],
+            ..Config::default()
+        };
+        let candidates = |config: &Config| {
+            let inputs = files::collect_inputs(&config.paths, &[], &Failures::default()).unwrap();
+            let kept = filter_inputs(config, inputs).unwrap();
+            kept.iter()
+                .map(|input| match input {
+                    Input::File { path } => path.strip_prefix(&root).unwrap().display().to_string(),
+                    Input::Stdin => String::from("-"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["poems/frog.txt"], candidates(&config));
+
+        // Files that changed or are new are searched until indexed again
+        fs::write(root.join("notes.txt"), "a frog in the notes\n").unwrap();
+        fs::write(root.join("poems/new.txt"), "no frogs\n").unwrap();
+        assert_eq!(
+            vec!["notes.txt", "poems/frog.txt", "poems/new.txt"],
+            candidates(&config)
+        );
+
+        fs::remove_file(root.join("poems/frog.txt")).unwrap();
+        assert_eq!(IndexStats { files: 3, read: 2, removed: 1 }, update_index(&root).unwrap());
+        assert_eq!(vec!["notes.txt", "poems/new.txt"], candidates(&config));
+    }
+}
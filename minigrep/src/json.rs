@@ -0,0 +1,114 @@
+// Records written by `--json`, one JSON object per line.
+//
+// Every record has a `type` field. For each file with at least one selected
+// line the output is a `begin` record, the `match` and `context` records of
+// that file in line order, and an `end` record:
+//
+//   {"type":"begin","path":"src/lib.rs"}
+//   {"type":"match","path":"src/lib.rs","line_number":3,"byte_offset":52,"line":"use std::fs;","submatches":[{"start":9,"end":11}]}
+//   {"type":"context","path":"src/lib.rs","line_number":4,"byte_offset":65,"line":""}
+//   {"type":"end","path":"src/lib.rs","matched_lines":1}
+//
+// - `path` is the file as it was found, `(standard input)` for stdin.
+// - `line_number` is 1-based, `byte_offset` is the offset of the start of the
+//   line in the file.
+// - `line` is the line without its line ending. Bytes that aren't valid in
+//   the input's encoding are replaced by U+FFFD. Binary input is written
+//   like text.
+// - `submatches` are the byte ranges of the matches within `line`, `end` is
+//   exclusive. It is empty for lines selected by `--invert-match`. With
+//   `--fuzzy` each submatch also has the `distance` of the match to the
+//   pattern that it was found for.
+// - `matched_lines` is the number of selected lines in the file.
+//
+// Fields are only ever added to this schema, never renamed or removed.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record<'a> {
+    Begin {
+        path: Cow<'a, str>,
+    },
+    Match {
+        path: Cow<'a, str>,
+        line_number: usize,
+        byte_offset: usize,
+        line: &'a str,
+        submatches: Vec<Submatch>,
+    },
+    Context {
+        path: Cow<'a, str>,
+        line_number: usize,
+        byte_offset: usize,
+        line: &'a str,
+    },
+    End {
+        path: Cow<'a, str>,
+        matched_lines: usize,
+    },
+}
+
+/// A part of a line that matched, with how far it is from the pattern for
+/// --fuzzy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Submatch {
+    pub start: usize,
+    pub end: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<usize>,
+}
+
+impl Submatch {
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+impl From<&Range<usize>> for Submatch {
+    fn from(span: &Range<usize>) -> Submatch {
+        Submatch { start: span.start, end: span.end, distance: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_layout() {
+        let record = Record::Match {
+            path: Cow::Borrowed("poem.txt"),
+            line_number: 2,
+            byte_offset: 30,
+            line: "Are you nobody, too?",
+            submatches: vec![Submatch::from(&(4..7))],
+        };
+        assert_eq!(
+            r#"{"type":"match","path":"poem.txt","line_number":2,"byte_offset":30,"line":"Are you nobody, too?","submatches":[{"start":4,"end":7}]}"#,
+            serde_json::to_string(&record).unwrap()
+        );
+
+        let record = Record::Match {
+            path: Cow::Borrowed("poem.txt"),
+            line_number: 2,
+            byte_offset: 30,
+            line: "Are you nobdy, too?",
+            submatches: vec![Submatch { start: 8, end: 13, distance: Some(1) }],
+        };
+        assert_eq!(
+            r#"{"type":"match","path":"poem.txt","line_number":2,"byte_offset":30,"line":"Are you nobdy, too?","submatches":[{"start":8,"end":13,"distance":1}]}"#,
+            serde_json::to_string(&record).unwrap()
+        );
+
+        let record = Record::End { path: Cow::Borrowed("poem.txt"), matched_lines: 1 };
+        assert_eq!(
+            r#"{"type":"end","path":"poem.txt","matched_lines":1}"#,
+            serde_json::to_string(&record).unwrap()
+        );
+    }
+}
@@ -1,13 +1,82 @@
-use std::error::Error;
-use std::fs;
+mod case;
+mod cli;
+mod color;
+mod context;
+mod decompress;
+mod encoding;
+mod error;
+mod extract;
+mod files;
+mod follow;
+mod fuzzy;
+mod index;
+mod json;
+mod matcher;
+mod multiline;
+mod parallel;
+mod printer;
+mod query;
+mod replace;
+mod searcher;
+mod sink;
+mod stream;
+mod structured;
+
 use std::env;
+use std::io::{self, BufRead, BufReader, IsTerminal, LineWriter, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::error::Failures;
+use crate::files::Input;
+use crate::printer::Printer;
+
+pub use crate::cli::{ArgsError, USAGE};
+pub use crate::color::ColorChoice;
+pub use crate::context::context_ranges;
+pub use crate::error::Error;
+pub use crate::extract::Aggregate;
+pub use crate::index::{update_index, IndexStats, INDEX_FILE};
+pub use crate::json::Submatch;
+pub use crate::query::{Expr, ParseError};
+pub use crate::searcher::{Searcher, SearcherBuilder};
+pub use crate::sink::Sink;
+pub use crate::structured::LogFormat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'a> {
+    /// 1-based, like grep -n
+    pub line_number: usize,
+    /// Offset of the start of the line within the searched contents
+    pub byte_offset: usize,
+    pub line: &'a str,
+}
+
+// Like `str::lines`, but keeps track of where each line starts
+pub(crate) fn numbered_lines(contents: &str) -> impl Iterator<Item = Match<'_>> {
+    let mut byte_offset = 0;
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    contents.split_inclusive('\n').enumerate().map(move |(index, raw)| {
+        let line = match raw.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => raw,
+        };
+        let entry = Match { line_number: index + 1, byte_offset, line };
+        byte_offset += raw.len();
+        entry
+    })
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
     let mut results = Vec::new();
 
-    for line in contents.lines() {
-        if line.contains(query) {
-            results.push(line);
+    for entry in numbered_lines(contents) {
+        if entry.line.contains(query) {
+            results.push(entry);
         }
     }
 
@@ -17,53 +86,366 @@ pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
 pub fn search_case_insensitive<'a>(
     query: &str,
     contents: &'a str,
-) -> Vec<&'a str> {
-    let query = query.to_lowercase();
+) -> Vec<Match<'a>> {
+    let query = case::fold(query);
+    let mut results = Vec::new();
+
+    for entry in numbered_lines(contents) {
+        if case::fold(entry.line).contains(&query) {
+            results.push(entry);
+        }
+    }
+
+    results
+}
+
+pub fn search_regex<'a>(pattern: &Regex, contents: &'a str) -> Vec<Match<'a>> {
+    let mut results = Vec::new();
+
+    for entry in numbered_lines(contents) {
+        if pattern.is_match(entry.line) {
+            results.push(entry);
+        }
+    }
+
+    results
+}
+
+/// A line with a part that is within some edit distance of the query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch<'a> {
+    pub line: Match<'a>,
+    /// Byte range of the closest part of the line
+    pub span: Range<usize>,
+    /// Levenshtein distance from that part to the query
+    pub distance: usize,
+}
+
+pub fn search_fuzzy<'a>(
+    query: &str,
+    contents: &'a str,
+    max_distance: usize,
+) -> Vec<FuzzyMatch<'a>> {
+    let fuzzy = fuzzy::Fuzzy::new(query, max_distance);
+    // A query that can be deleted within the distance matches anywhere, like
+    // on the command line. An empty line has no part to be closest, only the
+    // empty start of it.
+    let anywhere = (0..0, query.chars().count());
+    let mut results = Vec::new();
+
+    for entry in numbered_lines(contents) {
+        let closest = fuzzy.find_iter(entry.line).into_iter().min_by_key(|(_, d)| *d);
+        let closest = closest.or_else(|| fuzzy.is_match(entry.line).then(|| anywhere.clone()));
+        if let Some((span, distance)) = closest {
+            results.push(FuzzyMatch { line: entry, span, distance });
+        }
+    }
+
+    results
+}
+
+/// A match that can span lines, from `search_multiline`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultilineMatch<'a> {
+    /// Line numbers of the first and last line the match is on, 1-based
+    pub lines: RangeInclusive<usize>,
+    /// Byte range of the match in the searched contents
+    pub span: Range<usize>,
+    pub text: &'a str,
+}
+
+/// Matches `pattern` against all of `contents` rather than line by line. For
+/// `^` and `$` to match at line breaks `pattern` needs multi-line mode.
+pub fn search_multiline<'a>(pattern: &Regex, contents: &'a str) -> Vec<MultilineMatch<'a>> {
+    let lines: Vec<Match> = numbered_lines(contents).collect();
     let mut results = Vec::new();
 
-    for line in contents.lines() {
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
+    for found in pattern.find_iter(contents) {
+        let span = found.range();
+        if !multiline::on_a_line(contents, &span) {
+            continue;
         }
+        let indexes = multiline::line_indexes(&lines, &span);
+        let lines = indexes.start() + 1..=indexes.end() + 1;
+        results.push(MultilineMatch { lines, span, text: found.as_str() });
     }
 
     results
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path)?;
+/// Path that stands for standard input, also used when no PATH is given
+pub const STDIN_PATH: &str = "-";
+
+/// Returns whether any line was selected, which decides the exit status
+pub fn run(mut config: Config) -> Result<bool, Error> {
+    let stdout = io::stdout();
+    let no_color = env::var("NO_COLOR").ok();
+    config.color = config.color.resolve(stdout.is_terminal(), no_color.as_deref());
+
+    search_to(config, &mut stdout.lock())
+}
+
+/// Searches everything `config` points at and writes the results to `out`.
+/// Files are searched on `config.threads` threads when there is more than one.
+/// Returns whether any line was selected.
+///
+/// An input that can't be read is reported on stderr and the others are still
+/// searched, after which the result is `Error::Incomplete`. With `quiet` a
+/// selected line still makes it true.
+///
+/// `config` is checked and its patterns are compiled like `Config::build`
+/// does, so it can also be filled in by hand.
+pub fn search_to<W: Write>(config: Config, out: &mut W) -> Result<bool, Error> {
+    let searcher = Searcher::new(config)?;
+    let config = searcher.config();
+    if config.follow {
+        return follow_file(&searcher, out);
+    }
+
+    let failures = Failures::default();
+    let matched = search_inputs(&searcher, &failures, out)?;
+    // Like in grep, -q succeeds at a selected line whatever failed before it
+    if matched && config.quiet {
+        return Ok(true);
+    }
+    failures.finish(matched)
+}
+
+// Returns whether any line was selected in the inputs that could be searched
+fn search_inputs<W: Write>(
+    searcher: &Searcher,
+    failures: &Failures,
+    out: &mut W,
+) -> Result<bool, Error> {
+    let config = searcher.config();
+    let mut inputs = files::collect_inputs(&config.paths, &config.globs, failures)?;
+    if config.use_index {
+        inputs = index::filter_inputs(config, inputs)?;
+    }
+    if config.write || config.diff {
+        return replace::rewrite_files(config, searcher.matcher(), &inputs, failures, out);
+    }
+    if let Some(aggregate) = config.aggregate {
+        return extract::aggregate(searcher, aggregate, &inputs, failures, out);
+    }
+    let show_path = config.paths.len() > 1 || config.paths.iter().any(|p| Path::new(p).is_dir());
+
+    let threads = config.threads.unwrap_or_else(default_threads).min(inputs.len());
+    // -q is done at the first selected line, so there is nothing to parallelize
+    if threads <= 1 || config.quiet {
+        let mut printer = Printer::new(config, show_path, out);
+        let mut matched = false;
+        for input in &inputs {
+            matched |= failures.skip(searcher.search_input(input, &mut printer))? > 0;
+            if matched && config.quiet {
+                break;
+            }
+        }
+        return Ok(matched);
+    }
+
+    let separate_groups = (config.before_context > 0 || config.after_context > 0)
+        && !config.count
+        && !config.files_with_matches
+        && !config.files_without_match
+        && !config.json;
+    let separator = separate_groups.then(|| printer::group_separator(config));
+    let matched = AtomicBool::new(false);
+    let search = |input: &Input, buffer: &mut Vec<u8>| -> Result<(), Error> {
+        let mut printer = Printer::new(config, show_path, buffer);
+        let selected = failures.skip(searcher.search_input(input, &mut printer))?;
+        matched.fetch_or(selected > 0, Ordering::Relaxed);
+        Ok(())
+    };
+    parallel::search_ordered(&inputs, threads, separator.as_deref(), out, search)?;
+
+    Ok(matched.into_inner())
+}
+
+fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// --follow: searches the lines appended to the only PATH until -m lines are
+// selected, which may be never. Each line is written out as soon as it is
+// printed.
+fn follow_file<W: Write>(searcher: &Searcher, out: &mut W) -> Result<bool, Error> {
+    let config = searcher.config();
+    let path = Path::new(&config.paths[0]);
+    let reader = follow::Follow::open(path, follow::POLL_INTERVAL)
+        .map_err(|source| Error::Io { path: path.to_path_buf(), source })?;
+
+    let mut printer = Printer::new(config, false, LineWriter::new(out));
+    printer.begin(path);
+    let selected = open_input(config, BufReader::new(reader))
+        .and_then(|reader| searcher.search_lines(reader, &mut printer))
+        .map_err(|e| input_error(path, e))?;
+    Ok(selected > 0)
+}
 
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
+// Turns the raw input into the UTF-8 text that is searched. With -z compressed
+// input is decompressed first, then it is decoded as its BOM or --encoding says.
+fn open_input<'a, R: BufRead + 'a>(
+    config: &Config,
+    reader: R,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    let reader: Box<dyn BufRead + 'a> = if config.search_zip {
+        decompress::reader(reader)?
     } else {
-        search(&config.query, &contents)
+        Box::new(reader)
     };
+    Ok(Box::new(encoding::decoder(config.encoding, reader)))
+}
 
-    for line in results {
-        println!("{line}");
+// Sorts out an error from searching `path`. Reading never fails with a broken
+// pipe, that one comes from writing the results.
+fn input_error(path: &Path, source: io::Error) -> Error {
+    match source.kind() {
+        io::ErrorKind::BrokenPipe => Error::Output(source),
+        _ => Error::Io { path: path.to_path_buf(), source },
     }
+}
 
-    Ok(())
+// What the command line asks for
+pub enum Command {
+    Search(Box<Config>),
+    /// `minigrep index [DIR]`: build or update the index of DIR
+    Index { root: PathBuf },
 }
 
+impl Command {
+    pub fn build(args: &[String]) -> Result<Command, Error> {
+        if args.get(1).is_some_and(|arg| arg == cli::INDEX_COMMAND) {
+            return cli::parse_index(args).map(|root| Command::Index { root });
+        }
+        Config::build(args).map(|config| Command::Search(Box::new(config)))
+    }
+}
+
+/// The options of a search, which `Config::build` takes from the command line
+#[derive(Default)]
 pub struct Config {
-    pub query: String,
-    pub file_path: String,
+    /// Patterns to search for, a line matches when any of them matches
+    pub patterns: Vec<String>,
+    /// Files and directories to search, `-` is standard input
+    pub paths: Vec<String>,
+    /// Full Unicode case folding, so "STRASSE" finds "straße". Regex mode uses
+    /// the regex crate's simple folding, where a character only matches one
+    /// other character.
     pub ignore_case: bool,
+    /// Ignore case unless a pattern has an uppercase letter, decides ignore_case
+    pub smart_case: bool,
+    pub regex: bool,
+    pub word_regexp: bool,
+    pub line_regexp: bool,
+    /// -U: match the patterns against the whole input, so a match can span lines
+    pub multiline: bool,
+    /// Compiled from `patterns` in regex mode, by `Config::build` and `search_to`
+    pub pattern: Option<Regex>,
+    /// Patterns are --boolean queries rather than single patterns
+    pub boolean: bool,
+    /// Parsed from `patterns` with --boolean like `pattern`, any of the queries matches
+    pub query: Option<Expr>,
+    /// Parse each line as JSON or logfmt, the patterns are then conditions on
+    /// its fields (`level=error`)
+    pub log_format: Option<LogFormat>,
+    /// Fields printed instead of the whole line with --log-format
+    pub select: Vec<String>,
+    /// Warn about lines --log-format can't parse instead of skipping them quietly
+    pub report_invalid: bool,
+    /// Print this instead of each selected line, with the capture groups of
+    /// the match expanded (`$1`, `${name}`)
+    pub extract: Option<String>,
+    /// Or print this field instead: a number from 1 for the fields split by
+    /// `delimiter` (runs of whitespace by default), a name with --log-format
+    pub field: Option<String>,
+    pub delimiter: Option<String>,
+    /// Count the extracted values instead of printing them
+    pub aggregate: Option<Aggregate>,
+    /// Only the most common values with --aggregate
+    pub top: Option<usize>,
+    /// Decompress gzip, zstd and bzip2 input, recognized by its magic bytes
+    pub search_zip: bool,
+    /// Encoding of input without a BOM, None reads it as UTF-8
+    pub encoding: Option<&'static encoding_rs::Encoding>,
+    /// Skip the files that the index of a directory says can't match
+    pub use_index: bool,
+    /// Include/exclude globs (`*.rs`, `!target/**`) applied when walking directories
+    pub globs: Vec<String>,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub invert_match: bool,
+    pub count: bool,
+    pub files_with_matches: bool,
+    pub files_without_match: bool,
+    /// Stop reading a file after this many selected lines (-m)
+    pub max_count: Option<usize>,
+    /// No output at all, only the exit status tells whether a line was selected
+    pub quiet: bool,
+    /// Keep searching what is appended to the file, like `tail -f`
+    pub follow: bool,
+    /// Worker threads for searching several files, None picks one per CPU
+    pub threads: Option<usize>,
+    pub color: ColorChoice,
+    /// JSON Lines output, src/json.rs describes the format
+    pub json: bool,
+    /// Match within this Levenshtein distance of the patterns
+    pub fuzzy: Option<usize>,
+    /// Print matching lines with each match replaced by this, `$1` is a
+    /// capture group in regex mode
+    pub replace: Option<String>,
+    /// Apply the replacements to the files instead of printing them
+    pub write: bool,
+    /// Print the replacements as a unified diff
+    pub diff: bool,
 }
 
 impl Config {
-    pub fn build(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            return Err("not enough arguments");
-        }
+    pub fn build(args: &[String]) -> Result<Config, Error> {
+        let defaults = Config {
+            ignore_case: env::var("IGNORE_CASE").is_ok(),
+            regex: env::var("REGEX").is_ok(),
+            ..Config::default()
+        };
 
-        let query = args[1].clone();
-        let file_path = args[2].clone();
+        cli::parse(args, defaults)
+    }
 
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+    // Ignoring case folds the pattern and the line one character at a time,
+    // unlike the other modes. Folding a regex into a different number of
+    // characters would break its classes and capture groups.
+    fn compile_pattern(query: &str, ignore_case: bool) -> Result<Regex, String> {
+        RegexBuilder::new(query)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| format!("invalid regex '{query}': {e}"))
+    }
 
-        Ok(Config { query, file_path, ignore_case })
+    // Compiles all patterns into one alternation, anchored for -x. With -U `^`
+    // and `$` match at the start and end of every line.
+    fn compile_patterns(
+        patterns: &[String],
+        ignore_case: bool,
+        line_regexp: bool,
+        multiline: bool,
+    ) -> Result<Regex, String> {
+        let alternation: Vec<String> = patterns.iter().map(|p| format!("(?:{p})")).collect();
+        let mut query = alternation.join("|");
+        if line_regexp {
+            query = format!("^(?:{query})$");
+        }
+
+        let mut builder = RegexBuilder::new(&query);
+        builder.case_insensitive(ignore_case).multi_line(multiline);
+        builder.build().map_err(|e| {
+            // Point at the pattern that is wrong rather than the combined regex
+            patterns
+                .iter()
+                .find_map(|p| Config::compile_pattern(p, ignore_case).err())
+                .unwrap_or_else(|| e.to_string())
+        })
     }
 }
 
@@ -72,6 +454,10 @@ mod tests {
     use crate::Config;
     use super::*;
 
+    fn lines<'a>(matches: Vec<Match<'a>>) -> Vec<&'a str> {
+        matches.iter().map(|m| m.line).collect()
+    }
+
     #[test]
     fn case_sensitive() {
         let query = "duct";
@@ -81,7 +467,7 @@ safe, fast, productive.
 Pick three.
 Duct tape.";
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        assert_eq!(vec!["safe, fast, productive."], lines(search(query, contents)));
     }
 
     #[test]
@@ -95,10 +481,108 @@ Trust me.";
 
         assert_eq!(
             vec!["Rust:", "Trust me."],
-            search_case_insensitive(query, contents)
+            lines(search_case_insensitive(query, contents))
+        );
+    }
+
+    #[test]
+    fn case_insensitive_unicode() {
+        let query = "STRASSE";
+        let contents = "\
+Hauptstraße 1
+Bahnhofstrasse 2
+Marktplatz 3";
+
+        assert_eq!(
+            vec!["Hauptstraße 1", "Bahnhofstrasse 2"],
+            lines(search_case_insensitive(query, contents))
         );
     }
 
+    #[test]
+    fn fuzzy_search() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+        let results = search_fuzzy("prodcutive", contents, 2);
+        assert_eq!(1, results.len());
+        assert_eq!(2, results[0].line.line_number);
+        assert_eq!("productive", &results[0].line.line[results[0].span.clone()]);
+        assert_eq!(2, results[0].distance);
+
+        // "rust" in "Trust" is one edit away
+        let distances: Vec<usize> = search_fuzzy("Rust", contents, 1).iter().map(|m| m.distance).collect();
+        assert_eq!(vec![0, 1], distances);
+
+        // Deleting "ab" is within 3 edits, so empty lines match as well
+        let results = search_fuzzy("ab", "\nxyz\n", 3);
+        let found: Vec<_> = results.iter().map(|m| (m.line.line_number, m.span.clone(), m.distance)).collect();
+        assert_eq!(vec![(1, 0..0, 2), (2, 1..3, 2)], found);
+    }
+
+    #[test]
+    fn multiline_search() {
+        let contents = "\
+Rust:
+safe, fast,
+productive.
+Pick three.";
+        let pattern = RegexBuilder::new(r"fast,\s+productive").build().unwrap();
+        let results = search_multiline(&pattern, contents);
+        assert_eq!(1, results.len());
+        assert_eq!(2..=3, results[0].lines);
+        assert_eq!("fast,\nproductive", results[0].text);
+        assert_eq!(12..28, results[0].span);
+
+        let pattern = RegexBuilder::new(r"^\w+\.$").multi_line(true).build().unwrap();
+        let lines: Vec<_> = search_multiline(&pattern, contents).into_iter().map(|m| m.lines).collect();
+        assert_eq!(vec![3..=3], lines);
+    }
+
+    #[test]
+    fn regex_search() {
+        let pattern = Regex::new(r"^[\w ]+:$").unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three:
+Duct tape.";
+
+        assert_eq!(vec!["Rust:", "Pick three:"], lines(search_regex(&pattern, contents)));
+    }
+
+    #[test]
+    fn regex_case_insensitive() {
+        let pattern = Config::compile_pattern("^rust", true).unwrap();
+        let contents = "\
+Rust:
+Trust me.";
+
+        assert_eq!(vec!["Rust:"], lines(search_regex(&pattern, contents)));
+    }
+
+    #[test]
+    fn regex_case_folding_is_simple() {
+        let contents = "\
+Hauptstraße 1
+Bahnhofstrasse 2
+HAUPTSTRAẞE 3";
+
+        // Unlike without -E, one character doesn't match two
+        let pattern = Config::compile_pattern("STRASSE", true).unwrap();
+        assert_eq!(vec!["Bahnhofstrasse 2"], lines(search_regex(&pattern, contents)));
+        let pattern = Config::compile_pattern("straße", true).unwrap();
+        assert_eq!(vec!["Hauptstraße 1", "HAUPTSTRAẞE 3"], lines(search_regex(&pattern, contents)));
+    }
+
+    #[test]
+    fn regex_invalid_pattern() {
+        let result = Config::compile_pattern("(unclosed", false);
+        assert!(result.unwrap_err().starts_with("invalid regex '(unclosed'"));
+    }
+
     #[test]
     fn run_one_result() {
         let query = "duct";
@@ -107,22 +591,144 @@ Rust:
 safe, fast, productive.
 Pick three.";
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        assert_eq!(vec!["safe, fast, productive."], lines(search(query, contents)));
+    }
+
+    #[test]
+    fn match_positions() {
+        let contents = "Rust:\r\nsafe, fast, productive.\nPick three.\n";
+
+        assert_eq!(
+            vec![Match { line_number: 2, byte_offset: 7, line: "safe, fast, productive." }],
+            search("fast", contents)
+        );
     }
 
     #[test]
     fn config_correct() {
         let input =  [String::from("program"),String::from("query"), String::from("file_path")];
         let config = Config::build(&input).unwrap();
-        assert_eq!(config.query, "query");
-        assert_eq!(config.file_path, "file_path");
+        assert_eq!(config.patterns, vec!["query"]);
+        assert_eq!(config.paths, vec!["file_path"]);
+        assert!(config.globs.is_empty());
+    }
+
+    #[test]
+    fn config_globs() {
+        let input = ["program", "-g", "*.rs", "--glob", "!target/**", "query", "src"].map(String::from);
+        let config = Config::build(&input).unwrap();
+        assert_eq!(config.globs, vec!["*.rs", "!target/**"]);
+    }
+
+    #[test]
+    fn config_context_options() {
+        let input = ["program", "-n", "-C", "2", "-A", "4", "query", "file_path"].map(String::from);
+        let config = Config::build(&input).unwrap();
+        assert!(config.line_number);
+        assert!(!config.byte_offset);
+        assert_eq!(config.before_context, 2);
+        assert_eq!(config.after_context, 4);
+        assert_eq!(config.patterns, vec!["query"]);
+    }
+
+    #[test]
+    fn config_invalid_context() {
+        let input = ["program", "-A", "many", "query", "file_path"].map(String::from);
+        let config = Config::build(&input);
+        assert_eq!(config.err().unwrap().to_string(), "invalid number for '-A': 'many'");
     }
 
     #[test]
     fn config_no_args() {
         let input =  [String::from("program")];
         let config = Config::build(&input);
-        assert_eq!(config.err().unwrap(), "not enough arguments");
+        assert_eq!(config.err().unwrap().to_string(), "missing required argument QUERY");
+    }
+
+    #[test]
+    fn compressed_files() {
+        let fixture = |name: &str| format!("{}/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+        let config = |search_zip| Config {
+            patterns: vec![String::from("frog")],
+            paths: vec![fixture("poem.txt.gz"), fixture("poem.txt.zst"), fixture("poem.txt.bz2")],
+            search_zip,
+            count: true,
+            threads: Some(1),
+            ..Config::default()
+        };
+
+        let mut out = Vec::new();
+        assert!(search_to(config(true), &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        let counts: Vec<&str> = out.lines().map(|l| l.rsplit(':').next().unwrap()).collect();
+        assert_eq!(vec!["1", "1", "1"], counts);
+
+        // Without -z a compressed file is just binary data
+        assert!(!search_to(config(false), &mut Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn missing_path_next_to_matching_file() {
+        let poem = concat!(env!("CARGO_MANIFEST_DIR"), "/poem.txt");
+        let missing = concat!(env!("CARGO_MANIFEST_DIR"), "/does/not/exist.txt");
+        let config = |threads, quiet| Config {
+            patterns: vec![String::from("frog")],
+            paths: vec![String::from(missing), String::from(poem), String::from(missing)],
+            threads: Some(threads),
+            quiet,
+            ..Config::default()
+        };
+
+        for threads in [1, 2] {
+            let mut out = Vec::new();
+            let result = search_to(config(threads, false), &mut out);
+            assert!(matches!(result, Err(Error::Incomplete(2))));
+            assert_eq!(format!("{poem}:How public, like a frog\n"), String::from_utf8(out).unwrap());
+        }
+        assert!(search_to(config(1, true), &mut Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn colored_context_across_files() {
+        let poem = concat!(env!("CARGO_MANIFEST_DIR"), "/poem.txt");
+        let config = |threads| Config {
+            patterns: vec![String::from("frog")],
+            paths: vec![String::from(poem), String::from(poem)],
+            after_context: 1,
+            color: ColorChoice::Always,
+            threads: Some(threads),
+            ..Config::default()
+        };
+
+        let mut sequential = Vec::new();
+        search_to(config(1), &mut sequential).unwrap();
+        let mut parallel = Vec::new();
+        search_to(config(2), &mut parallel).unwrap();
+        let separator = format!("{}--{}\n", color::SEPARATOR, color::RESET);
+        assert!(String::from_utf8_lossy(&sequential).contains(&separator));
+        assert_eq!(String::from_utf8_lossy(&sequential), String::from_utf8_lossy(&parallel));
+    }
+
+    #[test]
+    fn config_filled_in_by_hand() {
+        let poem = concat!(env!("CARGO_MANIFEST_DIR"), "/poem.txt");
+        let config = |regex, smart_case| Config {
+            patterns: vec![String::from("^how")],
+            paths: vec![String::from(poem)],
+            regex,
+            smart_case,
+            count: true,
+            ..Config::default()
+        };
+
+        let mut out = Vec::new();
+        assert!(search_to(config(true, true), &mut out).unwrap());
+        assert_eq!("2\n", String::from_utf8(out).unwrap());
+        assert!(!search_to(config(true, false), &mut Vec::new()).unwrap());
+        assert!(!search_to(config(false, true), &mut Vec::new()).unwrap());
+
+        let config = Config { fuzzy: Some(1), ..config(true, false) };
+        assert!(matches!(search_to(config, &mut Vec::new()), Err(Error::Args(_))));
     }
 
 }
\ No newline at end of file
@@ -1,18 +1,60 @@
 use std::env;
+use std::io;
 use std::process;
 
-use minigrep::Config;
+use minigrep::{ArgsError, Command, Error, IndexStats, INDEX_FILE, USAGE};
+
+// grep compatible exit status: a line was selected, no line was selected, or
+// something went wrong
+const EXIT_MATCH: i32 = 0;
+const EXIT_NO_MATCH: i32 = 1;
+const EXIT_ERROR: i32 = 2;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    let config = Config::build(&args).unwrap_or_else(|err| {
+    let command = Command::build(&args).unwrap_or_else(|err| {
+        if let Error::Args(ArgsError::Help | ArgsError::Version) = err {
+            println!("{err}");
+            process::exit(EXIT_MATCH);
+        }
+
         eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+        if let Error::Args(ArgsError::Usage(_)) = err {
+            eprintln!("{USAGE}");
+            eprintln!("Try 'minigrep --help' for more information.");
+        }
+        process::exit(EXIT_ERROR);
     });
 
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+    let config = match command {
+        Command::Search(config) => *config,
+        Command::Index { root } => match minigrep::update_index(&root) {
+            Ok(IndexStats { files, read, removed }) => {
+                let path = root.join(INDEX_FILE);
+                println!("{}: {files} files, {read} read, {removed} removed", path.display());
+                process::exit(EXIT_MATCH);
+            }
+            Err(e) => {
+                eprintln!("Application error: {e}");
+                process::exit(EXIT_ERROR);
+            }
+        },
+    };
+
+    match minigrep::run(config) {
+        Ok(true) => process::exit(EXIT_MATCH),
+        Ok(false) => process::exit(EXIT_NO_MATCH),
+        // The reader went away, like `minigrep ... | head`, which isn't worth
+        // a message
+        Err(Error::Output(e)) if e.kind() == io::ErrorKind::BrokenPipe => {
+            process::exit(EXIT_ERROR)
+        }
+        // Each input that couldn't be searched was reported already
+        Err(Error::Incomplete(_)) => process::exit(EXIT_ERROR),
+        Err(e) => {
+            eprintln!("Application error: {e}");
+            process::exit(EXIT_ERROR);
+        }
     }
 }
@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use regex::Regex;
+
+use crate::fuzzy::{self, Fuzzy};
+use crate::json::Submatch;
+use crate::query::Expr;
+use crate::structured::{Condition, LogFormat, Record};
+use crate::{case, Config};
+
+// The patterns of a `Config`, prepared once so they can be applied line by
+// line. Literal patterns, however many, are found in a single pass with an
+// Aho-Corasick automaton; regex patterns are compiled into one alternation.
+// With --fuzzy each pattern is searched for approximately on its own, and
+// with --boolean each term of the query gets a matcher of its own. With
+// --log-format the patterns are conditions on the fields of each line.
+pub struct Matcher {
+    kind: Kind,
+    boundary: Boundary,
+}
+
+enum Kind {
+    Literal(AhoCorasick),
+    // Searches the case folded line for the case folded patterns
+    CaseInsensitive(AhoCorasick),
+    Regex(Regex),
+    // The patterns are case folded with `ignore_case`, like the line then is
+    Fuzzy { patterns: Vec<Fuzzy>, ignore_case: bool },
+    // The query is evaluated with the matcher of each term
+    Boolean { query: Expr, terms: HashMap<String, Matcher> },
+    // Any of the conditions holds for the parsed line
+    Fields { format: LogFormat, conditions: Vec<Condition> },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Boundary {
+    None,
+    // -w: no word character right before or after the match
+    Word,
+    // -x: the match is the whole line
+    Line,
+}
+
+impl Matcher {
+    pub fn new(config: &Config) -> Result<Matcher, String> {
+        if let Some(query) = &config.query {
+            return Matcher::boolean(config, query);
+        }
+        if let Some(format) = config.log_format {
+            let conditions: Result<Vec<Condition>, String> =
+                config.patterns.iter().map(|p| Condition::parse(p, config.ignore_case)).collect();
+            let kind = Kind::Fields { format, conditions: conditions? };
+            return Ok(Matcher { kind, boundary: Boundary::None });
+        }
+
+        let boundary = if config.line_regexp {
+            Boundary::Line
+        } else if config.word_regexp {
+            Boundary::Word
+        } else {
+            Boundary::None
+        };
+        // Boundaries are checked on every possible match, so the automaton
+        // has to report overlapping matches as well
+        let match_kind = match boundary {
+            Boundary::None => MatchKind::LeftmostLongest,
+            _ => MatchKind::Standard,
+        };
+        let mut builder = AhoCorasick::builder();
+        let automaton = builder.match_kind(match_kind);
+        let build = |patterns: Vec<String>| automaton.build(patterns).map_err(|e| e.to_string());
+
+        let kind = match &config.pattern {
+            Some(pattern) => Kind::Regex(pattern.clone()),
+            None if config.fuzzy.is_some() => {
+                let max_distance = config.fuzzy.unwrap_or_default();
+                let patterns = config.patterns.iter().map(|pattern| {
+                    let pattern =
+                        if config.ignore_case { case::fold(pattern) } else { pattern.clone() };
+                    Fuzzy::new(&pattern, max_distance)
+                });
+                Kind::Fuzzy { patterns: patterns.collect(), ignore_case: config.ignore_case }
+            }
+            None if config.ignore_case => {
+                let folded = config.patterns.iter().map(|p| case::fold(p));
+                Kind::CaseInsensitive(build(folded.collect())?)
+            }
+            None => Kind::Literal(build(config.patterns.clone())?),
+        };
+
+        Ok(Matcher { kind, boundary })
+    }
+
+    // Each term is matched like a single pattern with the rest of `config`
+    fn boolean(config: &Config, query: &Expr) -> Result<Matcher, String> {
+        let mut terms = HashMap::new();
+        for term in query.terms() {
+            let patterns = vec![term.to_string()];
+            let pattern = if config.regex {
+                let (ignore_case, line_regexp) = (config.ignore_case, config.line_regexp);
+                Some(Config::compile_patterns(&patterns, ignore_case, line_regexp, false)?)
+            } else {
+                None
+            };
+            let term_config = Config {
+                patterns,
+                pattern,
+                ignore_case: config.ignore_case,
+                word_regexp: config.word_regexp,
+                line_regexp: config.line_regexp,
+                fuzzy: config.fuzzy,
+                log_format: config.log_format,
+                ..Config::default()
+            };
+            terms.insert(term.to_string(), Matcher::new(&term_config)?);
+        }
+
+        let kind = Kind::Boolean { query: query.clone(), terms };
+        Ok(Matcher { kind, boundary: Boundary::None })
+    }
+
+    // Whether `line` matches, or why it couldn't be matched: with --log-format
+    // lines that can't be parsed don't match, and don't not match either
+    pub fn try_match(&self, line: &str) -> Result<bool, String> {
+        match &self.kind {
+            Kind::Fields { format, conditions } => {
+                let record = Record::parse(*format, line)?;
+                Ok(conditions.iter().any(|condition| condition.matches(&record)))
+            }
+            Kind::Boolean { query, terms } if self.parses_lines() => {
+                let mut invalid = None;
+                let matched = query.eval(&mut |term| {
+                    match terms.get(term).map(|m| m.try_match(line)) {
+                        Some(Ok(matched)) => matched,
+                        Some(Err(e)) => {
+                            invalid.get_or_insert(e);
+                            false
+                        }
+                        None => false,
+                    }
+                });
+                invalid.map_or(Ok(matched), Err)
+            }
+            _ => Ok(self.is_match(line)),
+        }
+    }
+
+    // Whether lines are parsed as structured logs before matching
+    fn parses_lines(&self) -> bool {
+        match &self.kind {
+            Kind::Fields { .. } => true,
+            Kind::Boolean { terms, .. } => terms.values().any(Matcher::parses_lines),
+            _ => false,
+        }
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match (&self.kind, self.boundary) {
+            (Kind::Literal(automaton), Boundary::None) => automaton.is_match(line),
+            (Kind::CaseInsensitive(automaton), Boundary::None) => {
+                automaton.is_match(&case::fold(line))
+            }
+            // A -x regex is anchored when it is compiled
+            (Kind::Regex(pattern), Boundary::None | Boundary::Line) => pattern.is_match(line),
+            (Kind::Fuzzy { patterns, ignore_case }, _) => {
+                let line = if *ignore_case { case::fold(line) } else { line.to_string() };
+                patterns.iter().any(|pattern| pattern.is_match(&line))
+            }
+            (Kind::Boolean { query, terms }, _) => {
+                query.eval(&mut |term| terms.get(term).is_some_and(|m| m.is_match(line)))
+            }
+            (Kind::Fields { .. }, _) => self.try_match(line).unwrap_or(false),
+            _ => !self.all_spans(line).is_empty(),
+        }
+    }
+
+    // Byte ranges of the non-empty matches in `line`, in order
+    pub fn find_spans(&self, line: &str) -> Vec<Range<usize>> {
+        let mut spans = self.all_spans(line);
+        spans.retain(|span| !span.is_empty());
+        spans
+    }
+
+    // Like `find_spans`, with the distance of each match for --fuzzy
+    pub fn find_submatches(&self, line: &str) -> Vec<Submatch> {
+        if let Kind::Fuzzy { .. } = self.kind {
+            let matches = self.fuzzy_matches(line).into_iter();
+            return matches
+                .filter(|(span, _)| !span.is_empty())
+                .map(|(span, distance)| Submatch { distance: Some(distance), ..(&span).into() })
+                .collect();
+        }
+        self.find_spans(line).iter().map(Submatch::from).collect()
+    }
+
+    // `line` with every match replaced by `replacement`. For a regex `$1` and
+    // `${name}` in `replacement` are capture groups, as in `Regex::replace_all`.
+    // Also returns where the replacements ended up in the new line.
+    pub fn replace(&self, line: &str, replacement: &str) -> (String, Vec<Range<usize>>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut spans = Vec::new();
+        let mut copied = 0;
+
+        for span in self.find_spans(line) {
+            replaced.push_str(&line[copied..span.start]);
+            let start = replaced.len();
+            match &self.kind {
+                // The span is where a match starts, the groups come from matching there again
+                Kind::Regex(pattern) => {
+                    if let Some(captures) = pattern.captures_at(line, span.start) {
+                        captures.expand(replacement, &mut replaced);
+                    }
+                }
+                _ => replaced.push_str(replacement),
+            }
+            spans.push(start..replaced.len());
+            copied = span.end;
+        }
+
+        replaced.push_str(&line[copied..]);
+        (replaced, spans)
+    }
+
+    // --extract: `template` expanded with the capture groups of the first
+    // match in `line`, as in `Captures::expand`. Only a regex has groups.
+    pub fn expand(&self, line: &str, template: &str) -> Option<String> {
+        let Kind::Regex(pattern) = &self.kind else {
+            return None;
+        };
+        let span = self.all_spans(line).into_iter().next()?;
+        let captures = pattern.captures_at(line, span.start)?;
+        let mut expanded = String::new();
+        captures.expand(template, &mut expanded);
+        Some(expanded)
+    }
+
+    // Like `find_spans`, but keeps empty matches, which still make a line match
+    pub fn all_spans(&self, line: &str) -> Vec<Range<usize>> {
+        let accept = |span: &Range<usize>| match self.boundary {
+            Boundary::None => true,
+            Boundary::Word => at_word_boundaries(line, span),
+            Boundary::Line => *span == (0..line.len()),
+        };
+
+        match &self.kind {
+            Kind::Literal(automaton) if self.boundary == Boundary::None => {
+                automaton.find_iter(line).map(|m| m.range()).collect()
+            }
+            Kind::Literal(automaton) => {
+                let candidates = automaton.find_overlapping_iter(line).map(|m| m.range());
+                leftmost_longest(candidates.filter(accept).collect())
+            }
+            Kind::CaseInsensitive(automaton) => {
+                let (folded, origins) = case::fold_with_origins(line);
+                if self.boundary == Boundary::None {
+                    let spans = automaton.find_iter(&folded).map(|m| m.range());
+                    return case::original_spans(&origins, spans);
+                }
+
+                let candidates = automaton
+                    .find_overlapping_iter(&folded)
+                    .map(|m| original_span(&origins, m.range(), line.len()));
+                leftmost_longest(candidates.filter(accept).collect())
+            }
+            Kind::Regex(pattern) if self.boundary == Boundary::Word => {
+                find_words(line, |start| pattern.find_at(line, start).map(|m| m.range()))
+            }
+            Kind::Regex(pattern) => pattern.find_iter(line).map(|m| m.range()).collect(),
+            Kind::Fuzzy { .. } => self.fuzzy_matches(line).into_iter().map(|(span, _)| span).collect(),
+            // The matches of the terms that made the line match, not the ones
+            // that are negated
+            Kind::Boolean { query, terms } if self.is_match(line) => {
+                let positive = query.positive_terms().into_iter().filter_map(|t| terms.get(t));
+                leftmost_longest(positive.flat_map(|m| m.all_spans(line)).collect())
+            }
+            Kind::Boolean { .. } => Vec::new(),
+            // Values in JSON can be escaped, they aren't highlighted
+            Kind::Fields { .. } => Vec::new(),
+        }
+    }
+
+    // The --fuzzy matches of all patterns with their distances. Where matches
+    // of different patterns overlap the one that starts first, or is closest,
+    // is kept.
+    fn fuzzy_matches(&self, line: &str) -> Vec<(Range<usize>, usize)> {
+        let Kind::Fuzzy { patterns, ignore_case } = &self.kind else {
+            return Vec::new();
+        };
+
+        let mut matches: Vec<(Range<usize>, usize)> = if *ignore_case {
+            let (folded, origins) = case::fold_with_origins(line);
+            patterns
+                .iter()
+                .flat_map(|pattern| pattern.find_iter(&folded))
+                .map(|(span, distance)| (original_span(&origins, span, line.len()), distance))
+                .collect()
+        } else {
+            patterns.iter().flat_map(|pattern| pattern.find_iter(line)).collect()
+        };
+        matches.sort_by_key(|(span, distance)| (span.start, *distance));
+        fuzzy::non_overlapping(matches)
+    }
+}
+
+// Maps a single span in the folded line back to the original line. An empty
+// span (from an empty pattern) has no character to map through.
+fn original_span(origins: &[Range<usize>], folded: Range<usize>, len: usize) -> Range<usize> {
+    if folded.is_empty() {
+        let at = origins.get(folded.start).map_or(len, |origin| origin.start);
+        return at..at;
+    }
+    origins[folded.start].start..origins[folded.end - 1].end
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn at_word_boundaries(line: &str, span: &Range<usize>) -> bool {
+    let before = line[..span.start].chars().next_back();
+    let after = line[span.end..].chars().next();
+    !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+}
+
+// Picks the leftmost, then longest, of the candidates that don't overlap an
+// earlier pick
+fn leftmost_longest(mut candidates: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    candidates.sort_by_key(|span| (span.start, usize::MAX - span.end));
+
+    let mut spans: Vec<Range<usize>> = Vec::new();
+    for span in candidates {
+        if spans.last().is_none_or(|last| span.start >= last.end && span != *last) {
+            spans.push(span);
+        }
+    }
+    spans
+}
+
+// Finds whole word matches of a regex: a match that isn't a whole word is
+// retried from the next character, where a different match might be.
+fn find_words(
+    line: &str,
+    find_at: impl Fn(usize) -> Option<Range<usize>>,
+) -> Vec<Range<usize>> {
+    let next_char = |at: usize| at + line[at..].chars().next().map_or(1, char::len_utf8);
+    let mut spans = Vec::new();
+    let mut start = 0;
+
+    while start <= line.len() {
+        let Some(span) = find_at(start) else {
+            break;
+        };
+        if at_word_boundaries(line, &span) {
+            start = if span.is_empty() { next_char(span.end) } else { span.end };
+            spans.push(span);
+        } else {
+            start = next_char(span.start);
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(patterns: &[&str], config: Config) -> Matcher {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect();
+        Matcher::new(&Config { patterns, ..config }).unwrap()
+    }
+
+    fn regex(pattern: &str, config: Config) -> Matcher {
+        let pattern = Some(Regex::new(pattern).unwrap());
+        matcher(&[], Config { pattern, ..config })
+    }
+
+    fn ignore_case() -> Config {
+        Config { ignore_case: true, ..Config::default() }
+    }
+
+    #[test]
+    fn same_results_as_search_functions() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        let m = matcher(&["rUsT"], ignore_case());
+        let lines: Vec<&str> = contents.lines().filter(|l| m.is_match(l)).collect();
+        assert_eq!(vec!["Rust:", "Trust me."], lines);
+
+        let m = matcher(&["Pick"], Config::default());
+        assert!(m.is_match("Pick three."));
+        assert!(!m.is_match("pick three."));
+    }
+
+    #[test]
+    fn spans() {
+        assert_eq!(vec![0..2, 3..5], matcher(&["ab"], Config::default()).find_spans("ab ab a"));
+        assert_eq!(vec![1..3], regex("x*", Config::default()).find_spans("axxb"));
+    }
+
+    #[test]
+    fn case_insensitive_spans_in_original_line() {
+        let m = matcher(&["rUsT"], ignore_case());
+        assert_eq!(vec![0..4, 7..11], m.find_spans("Rust, trust"));
+
+        // 'İ' is two bytes but folds to three: "i̇"
+        assert_eq!(vec![2..5], matcher(&["i̇x"], ignore_case()).find_spans("a İX"));
+
+        let m = matcher(&["STRASSE"], ignore_case());
+        assert!(m.is_match("Die Straße"));
+        assert_eq!(vec![4..11], m.find_spans("Die Straße"));
+    }
+
+    #[test]
+    fn boolean_queries() {
+        let boolean = |query: &str, config: Config| {
+            Matcher::new(&Config { query: Some(Expr::parse(query).unwrap()), ..config }).unwrap()
+        };
+
+        let m = boolean("error AND (db OR cache) AND NOT timeout", Config::default());
+        assert!(m.is_match("error: db connection lost"));
+        assert!(!m.is_match("error: db timeout"));
+        assert!(!m.is_match("Error: db connection lost"));
+        assert_eq!(vec![0..5, 7..9], m.find_spans("error: db connection lost"));
+        assert!(m.find_spans("error: db timeout").is_empty());
+
+        // Terms are matched with the options of the search
+        let m = boolean("ERROR AND NOT db", Config { word_regexp: true, ..ignore_case() });
+        assert!(m.is_match("Error in dbms"));
+        assert!(!m.is_match("Errors in dbms"));
+        let m = boolean(r"err\w+ OR NOT \d", Config { regex: true, ..Config::default() });
+        assert!(m.is_match("errno 5"));
+        assert!(m.is_match("no digits"));
+        assert!(!m.is_match("err 5"));
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let m = matcher(&["error", "warn", "warning"], Config::default());
+        assert!(m.is_match("a warning"));
+        assert!(!m.is_match("all good"));
+        assert_eq!(vec![2..9, 14..19], m.find_spans("a warning and error"));
+
+        let m = matcher(&["ERROR", "straße"], ignore_case());
+        assert_eq!(vec![0..5, 9..16], m.find_spans("error in STRASSE"));
+    }
+
+    #[test]
+    fn no_patterns_never_match() {
+        assert!(!matcher(&[], Config::default()).is_match("anything"));
+    }
+
+    #[test]
+    fn whole_words() {
+        let word = || Config { word_regexp: true, ..Config::default() };
+
+        let m = matcher(&["foo", "foo-"], word());
+        assert!(!m.is_match("foobar"));
+        assert_eq!(vec![0..4], m.find_spans("foo- bar"));
+        assert_eq!(vec![0..3], m.find_spans("foo-bar"));
+        assert_eq!(vec![5..8], m.find_spans("food foo"));
+
+        let m = matcher(&["straße"], Config { word_regexp: true, ..ignore_case() });
+        assert_eq!(vec![4..11], m.find_spans("Die STRASSE, Hauptstraße"));
+
+        let m = regex(r"fo+", word());
+        assert_eq!(vec![6..9], m.find_spans("fooo_ foo"));
+    }
+
+    #[test]
+    fn replacements() {
+        let m = matcher(&["rust"], ignore_case());
+        assert_eq!((String::from("C, tC"), vec![0..1, 4..5]), m.replace("Rust, trust", "C"));
+
+        let m = regex(r"(?P<key>\w+)=(\d+)", Config::default());
+        let (replaced, spans) = m.replace("a=1, b=22 c=x", "$2:${key}");
+        assert_eq!("1:a, 22:b c=x", replaced);
+        assert_eq!(vec![0..3, 5..9], spans);
+
+        // Groups of a -w match, which isn't the first match of the regex
+        let m = regex(r"(\w)o+", Config { word_regexp: true, ..Config::default() });
+        assert_eq!("foobar $1", m.replace("foobar boo", "$$1").0);
+        assert_eq!("foobar b", m.replace("foobar boo", "$1").0);
+    }
+
+    #[test]
+    fn fuzzy_matches() {
+        let m = matcher(&["connection", "timeout"], Config { fuzzy: Some(1), ..Config::default() });
+        assert!(m.is_match("lost conection"));
+        assert!(!m.is_match("lost conecton"));
+        assert_eq!(
+            vec![
+                Submatch { start: 0, end: 6, distance: Some(1) },
+                Submatch { start: 10, end: 20, distance: Some(0) },
+            ],
+            m.find_submatches("tmeout on connection")
+        );
+
+        let m = matcher(&["STRASE"], Config { fuzzy: Some(1), ..ignore_case() });
+        assert_eq!(vec![4..11], m.find_spans("Die Straße"));
+    }
+
+    #[test]
+    fn whole_lines() {
+        let m = matcher(&["foo", ""], Config { line_regexp: true, ..Config::default() });
+        assert!(m.is_match("foo"));
+        assert!(m.is_match(""));
+        assert!(!m.is_match("foo bar"));
+        assert_eq!(vec![0..3], m.find_spans("foo"));
+    }
+}
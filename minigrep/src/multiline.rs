@@ -0,0 +1,164 @@
+use std::io::{self, BufRead};
+use std::ops::{Range, RangeInclusive};
+
+use crate::context::context_ranges;
+use crate::json::Submatch;
+use crate::matcher::Matcher;
+use crate::sink::Sink;
+use crate::{numbered_lines, Config, Match};
+
+// -U: the patterns are matched against the whole input instead of one line at
+// a time, so a match can span lines, like a function signature split over
+// several. Every line a match touches is selected. The input has to be read
+// into memory for this, unlike in `stream`.
+
+// Zero-based indexes of the first and last of `lines` that `span` touches.
+// `lines` are all lines of the contents `span` is in.
+pub fn line_indexes(lines: &[Match], span: &Range<usize>) -> RangeInclusive<usize> {
+    let line_of = |offset: usize| lines.partition_point(|l| l.byte_offset <= offset) - 1;
+    let last = if span.is_empty() { span.start } else { span.end - 1 };
+    line_of(span.start)..=line_of(last)
+}
+
+// Whether `span` is on a line of `contents`, an empty match after the final
+// line break isn't
+pub fn on_a_line(contents: &str, span: &Range<usize>) -> bool {
+    span.start < contents.len() || !(contents.is_empty() || contents.ends_with('\n'))
+}
+
+// Like `stream::search_reader`, returns the number of selected lines. -m
+// limits the number of matches rather than lines.
+pub fn search_reader<R: BufRead, S: Sink>(
+    config: &Config,
+    matcher: &Matcher,
+    mut reader: R,
+    sink: &mut S,
+) -> io::Result<usize> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let binary = bytes.contains(&0);
+    let contents = String::from_utf8_lossy(&bytes);
+    let lines: Vec<Match> = numbered_lines(&contents).collect();
+
+    let spans = matcher.all_spans(&contents).into_iter().filter(|span| on_a_line(&contents, span));
+    // The selected lines in order, with the parts of them that matched
+    let mut selected: Vec<(usize, Vec<Submatch>)> = Vec::new();
+    for span in spans.take(config.max_count.unwrap_or(usize::MAX)) {
+        for index in line_indexes(&lines, &span) {
+            let line = &lines[index];
+            let clip = |offset: usize| {
+                offset.clamp(line.byte_offset, line.byte_offset + line.line.len()) - line.byte_offset
+            };
+            let part = clip(span.start)..clip(span.end);
+
+            if selected.last().is_none_or(|(last, _)| *last != index) {
+                selected.push((index, Vec::new()));
+            }
+            if let Some((_, submatches)) = selected.last_mut().filter(|_| !part.is_empty()) {
+                submatches.push(Submatch::from(&part));
+            }
+        }
+    }
+
+    let count = selected.len();
+    let list_files = config.files_with_matches || config.files_without_match;
+    if count == 0 || config.count || list_files || config.quiet {
+        return Ok(count);
+    }
+    // JSON can carry any line, so it is written like any other
+    if binary && !config.json {
+        sink.binary_match()?;
+        return Ok(count);
+    }
+
+    let selected_lines: Vec<Match> = selected.iter().map(|&(index, _)| lines[index]).collect();
+    let has_context = config.before_context > 0 || config.after_context > 0;
+    let mut selected = selected.into_iter().peekable();
+    let ranges =
+        context_ranges(&selected_lines, lines.len(), config.before_context, config.after_context);
+
+    for range in ranges {
+        if has_context {
+            sink.context_break()?;
+        }
+        for index in range {
+            match selected.next_if(|&(i, _)| i == index) {
+                Some((_, submatches)) => sink.matched(&lines[index], &submatches)?,
+                None => sink.context(&lines[index])?,
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use regex::RegexBuilder;
+
+    use super::*;
+    use crate::printer::Printer;
+
+    fn search(pattern: &str, config: Config, contents: &str) -> (usize, String) {
+        let pattern = RegexBuilder::new(pattern).multi_line(true).build().unwrap();
+        let config = Config { pattern: Some(pattern), multiline: true, ..config };
+        let matcher = Matcher::new(&config).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&config, false, &mut out);
+        printer.begin(Path::new("poem.txt"));
+        let selected = search_reader(&config, &matcher, contents.as_bytes(), &mut printer).unwrap();
+        (selected, String::from_utf8(out).unwrap())
+    }
+
+    const SOURCE: &str = "\
+use std::io;
+
+fn short() {}
+
+fn search(
+    config: &Config,
+) -> usize {
+    0
+}
+";
+
+    #[test]
+    fn matches_span_lines() {
+        let config = Config { line_number: true, ..Config::default() };
+        let (selected, out) = search(r"fn \w+\(\s*\n[^)]*\)", config, SOURCE);
+        assert_eq!(3, selected);
+        assert_eq!("5:fn search(\n6:    config: &Config,\n7:) -> usize {\n", out);
+    }
+
+    #[test]
+    fn context_and_max_count() {
+        let config = Config { after_context: 1, max_count: Some(1), ..Config::default() };
+        let (selected, out) = search(r"^fn \w+\(", config, SOURCE);
+        assert_eq!(1, selected);
+        assert_eq!("fn short() {}\n\n", out);
+
+        let config = Config { before_context: 1, ..Config::default() };
+        let (_, out) = search(r"\{\}\n\nfn", config, SOURCE);
+        assert_eq!("\nfn short() {}\n\nfn search(\n", out);
+    }
+
+    #[test]
+    fn empty_matches_on_lines() {
+        let config = Config { line_number: true, ..Config::default() };
+        // `$` also matches after the final line break, which isn't a line
+        assert_eq!((2, String::from("2:\n4:\n")), search("^$", config, "a\n\nb\n\n"));
+    }
+
+    #[test]
+    fn line_ranges() {
+        let contents = "one\ntwo\nthree";
+        let lines: Vec<Match> = numbered_lines(contents).collect();
+        assert_eq!(0..=0, line_indexes(&lines, &(0..4)));
+        assert_eq!(0..=1, line_indexes(&lines, &(2..5)));
+        assert_eq!(2..=2, line_indexes(&lines, &(13..13)));
+        assert!(on_a_line(contents, &(13..13)));
+        assert!(!on_a_line("one\n", &(4..4)));
+    }
+}
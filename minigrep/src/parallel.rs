@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+// Runs `search` for every input on `threads` worker threads. Each input is
+// searched into its own buffer, and the buffers are written to `out` in input
+// order as soon as all earlier inputs are done, so the output is the same as
+// searching one input after the other.
+//
+// With a `separator` it is written between the output of two inputs, which
+// continues the `--` lines between context groups across files like grep does.
+//
+// An error from `search` stops the search once the output of the inputs
+// before it is written. Errors that only mean skipping the input, like one
+// that can't be read, have to be handled by `search` itself.
+pub fn search_ordered<T, F, W, E>(
+    inputs: &[T],
+    threads: usize,
+    separator: Option<&[u8]>,
+    out: &mut W,
+    search: F,
+) -> Result<(), E>
+where
+    T: Sync,
+    F: Fn(&T, &mut Vec<u8>) -> Result<(), E> + Sync,
+    W: Write,
+    E: From<io::Error> + Send,
+{
+    let next_input = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (next_input, search) = (&next_input, &search);
+            scope.spawn(move || loop {
+                let index = next_input.fetch_add(1, Ordering::Relaxed);
+                let Some(input) = inputs.get(index) else {
+                    break;
+                };
+
+                let mut buffer = Vec::new();
+                let result = search(input, &mut buffer).map(|()| buffer);
+                // The receiver is gone when writing failed, stop searching
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut next_to_write = 0;
+        let mut wrote_output = false;
+
+        for (index, result) in receiver {
+            pending.insert(index, result);
+
+            while let Some(result) = pending.remove(&next_to_write) {
+                let buffer = result?;
+                if !buffer.is_empty() {
+                    if let Some(separator) = separator.filter(|_| wrote_output) {
+                        out.write_all(separator)?;
+                    }
+                    out.write_all(&buffer)?;
+                    wrote_output = true;
+                }
+                next_to_write += 1;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn output_follows_input_order() {
+        // Earlier inputs take longer, so they finish last
+        let inputs: Vec<u64> = (0..8).collect();
+        let mut out = Vec::new();
+
+        search_ordered(&inputs, 4, None, &mut out, |&input, buffer| {
+            thread::sleep(Duration::from_millis((8 - input) * 5));
+            writeln!(buffer, "{input}")
+        })
+        .unwrap();
+
+        assert_eq!("0\n1\n2\n3\n4\n5\n6\n7\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn groups_separated_between_inputs() {
+        let inputs = ["a", "", "b"];
+        let mut out = Vec::new();
+
+        search_ordered(&inputs, 2, Some(b"--\n"), &mut out, |input, buffer| {
+            buffer.write_all(input.as_bytes())
+        })
+        .unwrap();
+
+        assert_eq!("a--\nb", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn first_error_in_order_is_returned() {
+        let inputs = [1, 2, 3];
+        let mut out = Vec::new();
+
+        let result = search_ordered(&inputs, 3, None, &mut out, |&input, buffer| {
+            if input == 2 {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "missing"));
+            }
+            writeln!(buffer, "{input}")
+        });
+
+        assert_eq!("missing", result.unwrap_err().to_string());
+        assert_eq!("1\n", String::from_utf8(out).unwrap());
+    }
+}
@@ -0,0 +1,222 @@
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::color::{self, ColorChoice};
+use crate::json::{Record, Submatch};
+use crate::sink::Sink;
+use crate::{Config, Match};
+
+pub struct Printer<W: Write> {
+    out: W,
+    show_path: bool,
+    line_number: bool,
+    byte_offset: bool,
+    count: bool,
+    files_with_matches: bool,
+    files_without_match: bool,
+    quiet: bool,
+    json: bool,
+    color: bool,
+    report_invalid: bool,
+    printed_group: bool,
+    // The file currently being searched, and whether its JSON begin record
+    // has been written yet
+    path: String,
+    begun: bool,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(config: &Config, show_path: bool, out: W) -> Printer<W> {
+        Printer {
+            out,
+            show_path,
+            line_number: config.line_number,
+            byte_offset: config.byte_offset,
+            count: config.count,
+            files_with_matches: config.files_with_matches,
+            files_without_match: config.files_without_match,
+            quiet: config.quiet,
+            json: config.json,
+            // `Auto` is resolved by `run`, any other writer gets plain text
+            color: config.color == ColorChoice::Always && !config.json,
+            report_invalid: config.report_invalid,
+            printed_group: false,
+            path: String::new(),
+            begun: false,
+        }
+    }
+
+    // grep style prefixes: `path:12:340:line` for matches, `path-13-372-line` for context
+    fn print_line(
+        &mut self,
+        line: &Match,
+        separator: char,
+        spans: &[Range<usize>],
+    ) -> io::Result<()> {
+        if self.show_path {
+            self.print_path()?;
+            self.paint(color::SEPARATOR, separator)?;
+        }
+        if self.line_number {
+            self.paint(color::LINE_NUMBER, line.line_number)?;
+            self.paint(color::SEPARATOR, separator)?;
+        }
+        if self.byte_offset {
+            self.paint(color::LINE_NUMBER, line.byte_offset)?;
+            self.paint(color::SEPARATOR, separator)?;
+        }
+
+        let mut printed = 0;
+        for span in spans {
+            write!(self.out, "{}", &line.line[printed..span.start])?;
+            self.paint(color::MATCH, &line.line[span.clone()])?;
+            printed = span.end;
+        }
+        writeln!(self.out, "{}", &line.line[printed..])
+    }
+
+    fn print_path(&mut self) -> io::Result<()> {
+        if self.color {
+            write!(self.out, "{}{}{}", color::PATH, self.path, color::RESET)
+        } else {
+            write!(self.out, "{}", self.path)
+        }
+    }
+
+    fn paint(&mut self, style: &str, text: impl Display) -> io::Result<()> {
+        if self.color {
+            write!(self.out, "{style}{text}{}", color::RESET)
+        } else {
+            write!(self.out, "{text}")
+        }
+    }
+
+    fn print_separator(&mut self) -> io::Result<()> {
+        self.paint(color::SEPARATOR, "--")?;
+        writeln!(self.out)
+    }
+
+    // Writes the begin record of the current file before its first line
+    fn begin_json(&mut self) -> io::Result<()> {
+        if !self.begun {
+            self.begun = true;
+            write_record(&mut self.out, &Record::Begin { path: self.path.as_str().into() })?;
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+// The output of the command line: grep style lines, counts, file names or
+// JSON records, as `Config` asks for
+impl<W: Write> Sink for Printer<W> {
+    fn begin(&mut self, path: &Path) {
+        self.path = path.display().to_string();
+        self.begun = false;
+    }
+
+    fn matched(&mut self, line: &Match, submatches: &[Submatch]) -> io::Result<()> {
+        if self.json {
+            self.begin_json()?;
+            let record = Record::Match {
+                path: self.path.as_str().into(),
+                line_number: line.line_number,
+                byte_offset: line.byte_offset,
+                line: line.line,
+                submatches: submatches.to_vec(),
+            };
+            return write_record(&mut self.out, &record);
+        }
+        let spans: Vec<Range<usize>> = submatches.iter().map(Submatch::span).collect();
+        self.print_line(line, ':', &spans)
+    }
+
+    fn context(&mut self, line: &Match) -> io::Result<()> {
+        if self.json {
+            self.begin_json()?;
+            let record = Record::Context {
+                path: self.path.as_str().into(),
+                line_number: line.line_number,
+                byte_offset: line.byte_offset,
+                line: line.line,
+            };
+            return write_record(&mut self.out, &record);
+        }
+        self.print_line(line, '-', &[])
+    }
+
+    // Groups are separated by `--`
+    fn context_break(&mut self) -> io::Result<()> {
+        if self.printed_group && !self.json {
+            self.print_separator()?;
+        }
+        self.printed_group = true;
+        Ok(())
+    }
+
+    // Printed instead of the lines of binary input, grep style
+    fn binary_match(&mut self) -> io::Result<()> {
+        writeln!(self.out, "Binary file {} matches", self.path)
+    }
+
+    fn invalid(&mut self, line_number: usize, reason: &str) {
+        if self.report_invalid {
+            report_invalid(&self.path, line_number, reason);
+        }
+    }
+
+    fn end(&mut self, selected: usize) -> io::Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
+        if self.json {
+            if self.begun {
+                let path = self.path.as_str().into();
+                write_record(&mut self.out, &Record::End { path, matched_lines: selected })?;
+            }
+        } else if self.files_with_matches || self.files_without_match {
+            if (selected > 0) == self.files_with_matches {
+                self.print_path()?;
+                writeln!(self.out)?;
+            }
+        } else if self.count {
+            if self.show_path {
+                self.print_path()?;
+                self.paint(color::SEPARATOR, ':')?;
+            }
+            writeln!(self.out, "{selected}")?;
+        }
+        Ok(())
+    }
+
+    // Only colors and JSON show where a line matched
+    fn wants_submatches(&self) -> bool {
+        self.color || self.json
+    }
+}
+
+// The `--` line between context groups, for output that is put together from
+// more than one printer
+pub fn group_separator(config: &Config) -> Vec<u8> {
+    let mut printer = Printer::new(config, false, Vec::new());
+    // Writing to a Vec can't fail
+    printer.print_separator().unwrap();
+    printer.out
+}
+
+// A line that couldn't be searched, which goes to stderr rather than between
+// the results
+pub fn report_invalid(path: &str, line_number: usize, reason: &str) {
+    eprintln!("minigrep: {path}:{line_number}: skipped, {reason}");
+}
+
+fn write_record<W: Write>(out: &mut W, record: &Record) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, record)?;
+    writeln!(out)
+}
@@ -0,0 +1,353 @@
+use std::error;
+use std::fmt;
+use std::ops::Range;
+
+/// --boolean queries: terms combined with AND, OR and NOT, and grouped with
+/// parentheses, as in `error AND (db OR cache) AND NOT timeout`. NOT binds
+/// tightest, then AND, then OR, so `a OR b AND NOT c` is `a OR (b AND (NOT c))`.
+///
+/// A term is a word, or a "quoted string" for spaces, parentheses or one of the
+/// operators themselves (\" and \\ escape inside quotes). Operators are only
+/// recognized in uppercase, `and` is a term. Each term is matched like a
+/// pattern would be, so it is a regex with -E and a whole word with -w.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Term(String),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    pub fn parse(query: &str) -> Result<Expr, ParseError> {
+        let mut parser = Parser { tokens: tokenize(query)?, next: 0, end: query.len() };
+        if parser.tokens.is_empty() {
+            return Err(ParseError::new("empty query", 0..0));
+        }
+
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token @ Token { kind: Kind::CloseParen, .. }) => {
+                Err(ParseError::new("unmatched ')'", token.span.clone()))
+            }
+            Some(token) => Err(ParseError::new(
+                format!("expected AND or OR before {}", token.kind),
+                token.span.clone(),
+            )),
+        }
+    }
+
+    /// Evaluates the expression with `is_match` deciding whether a term matches
+    pub fn eval(&self, is_match: &mut impl FnMut(&str) -> bool) -> bool {
+        match self {
+            Expr::Term(term) => is_match(term),
+            Expr::Not(expr) => !expr.eval(is_match),
+            Expr::And(exprs) => exprs.iter().all(|expr| expr.eval(is_match)),
+            Expr::Or(exprs) => exprs.iter().any(|expr| expr.eval(is_match)),
+        }
+    }
+
+    /// All terms, in the order they were written
+    pub fn terms(&self) -> Vec<&str> {
+        match self {
+            Expr::Term(term) => vec![term.as_str()],
+            Expr::Not(expr) => expr.terms(),
+            Expr::And(exprs) | Expr::Or(exprs) => exprs.iter().flat_map(Expr::terms).collect(),
+        }
+    }
+
+    /// The terms that aren't negated, which are what makes a line match
+    pub fn positive_terms(&self) -> Vec<&str> {
+        match self {
+            Expr::Term(term) => vec![term.as_str()],
+            Expr::Not(expr) => match expr.as_ref() {
+                Expr::Not(expr) => expr.positive_terms(),
+                _ => Vec::new(),
+            },
+            Expr::And(exprs) | Expr::Or(exprs) => {
+                exprs.iter().flat_map(Expr::positive_terms).collect()
+            }
+        }
+    }
+}
+
+// Written back as a query with every group in parentheses, which shows how it
+// was parsed
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, exprs: &[Expr], op: &str| {
+            write!(f, "(")?;
+            for (index, expr) in exprs.iter().enumerate() {
+                if index > 0 {
+                    write!(f, " {op} ")?;
+                }
+                write!(f, "{expr}")?;
+            }
+            write!(f, ")")
+        };
+
+        match self {
+            Expr::Term(term) if is_word(term) => write!(f, "{term}"),
+            Expr::Term(term) => {
+                write!(f, "\"{}\"", term.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+            Expr::Not(expr) => write!(f, "NOT {expr}"),
+            Expr::And(exprs) => join(f, exprs, "AND"),
+            Expr::Or(exprs) => join(f, exprs, "OR"),
+        }
+    }
+}
+
+/// What is wrong with a query, and the byte range of the token it is about
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Range<usize>,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, span: Range<usize>) -> ParseError {
+        ParseError { message: message.into(), span }
+    }
+
+    /// The message with the query below it, and the token marked under that:
+    ///
+    /// ```text
+    /// invalid query: expected a term, found ')'
+    ///   error AND )
+    ///             ^
+    /// ```
+    pub fn render(&self, query: &str) -> String {
+        let column = query[..self.span.start].chars().count();
+        let width = query[self.span.clone()].chars().count().max(1);
+        format!(
+            "invalid query: {}\n  {query}\n  {}{}",
+            self.message,
+            " ".repeat(column),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Kind {
+    Term(String),
+    And,
+    Or,
+    Not,
+    OpenParen,
+    CloseParen,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Term(term) => write!(f, "'{term}'"),
+            Kind::And => write!(f, "AND"),
+            Kind::Or => write!(f, "OR"),
+            Kind::Not => write!(f, "NOT"),
+            Kind::OpenParen => write!(f, "'('"),
+            Kind::CloseParen => write!(f, "')'"),
+        }
+    }
+}
+
+struct Token {
+    kind: Kind,
+    span: Range<usize>,
+}
+
+// Characters that end an unquoted term
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')' || c == '"'
+}
+
+// Whether `term` can be written without quotes
+fn is_word(term: &str) -> bool {
+    !term.is_empty() && !term.contains(is_delimiter) && !matches!(term, "AND" | "OR" | "NOT")
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let kind = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Kind::OpenParen,
+            ')' => Kind::CloseParen,
+            '"' => {
+                let mut term = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next_if(|&(_, c)| c == '"' || c == '\\') {
+                            Some((_, escaped)) => term.push(escaped),
+                            None => term.push('\\'),
+                        },
+                        Some((_, c)) => term.push(c),
+                        None => {
+                            return Err(ParseError::new("unterminated quote", start..query.len()))
+                        }
+                    }
+                }
+                Kind::Term(term)
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((at, c)) = chars.next_if(|&(_, c)| !is_delimiter(c)) {
+                    end = at + c.len_utf8();
+                }
+                match &query[start..end] {
+                    "AND" => Kind::And,
+                    "OR" => Kind::Or,
+                    "NOT" => Kind::Not,
+                    word => Kind::Term(word.to_string()),
+                }
+            }
+        };
+        let end = chars.peek().map_or(query.len(), |&(at, _)| at);
+        tokens.push(Token { kind, span: start..end });
+    }
+
+    Ok(tokens)
+}
+
+// Recursive descent, one method per precedence level:
+//
+//   or   = and { OR and }
+//   and  = not { AND not }
+//   not  = NOT not | atom
+//   atom = TERM | ( or )
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    // Where errors about a missing token point
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn eat(&mut self, kind: Kind) -> bool {
+        let found = self.peek().is_some_and(|token| token.kind == kind);
+        self.next += usize::from(found);
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut exprs = vec![self.and()?];
+        while self.eat(Kind::Or) {
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 { exprs.remove(0) } else { Expr::Or(exprs) })
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut exprs = vec![self.not()?];
+        while self.eat(Kind::And) {
+            exprs.push(self.not()?);
+        }
+        Ok(if exprs.len() == 1 { exprs.remove(0) } else { Expr::And(exprs) })
+    }
+
+    fn not(&mut self) -> Result<Expr, ParseError> {
+        if self.eat(Kind::Not) {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        let Some(token) = self.tokens.get(self.next) else {
+            let after = self.tokens.last().map(|token| token.kind.to_string());
+            let message = format!("expected a term after {}", after.unwrap_or_default());
+            return Err(ParseError::new(message, self.end..self.end));
+        };
+        self.next += 1;
+
+        match &token.kind {
+            Kind::Term(term) => Ok(Expr::Term(term.clone())),
+            Kind::OpenParen => {
+                let open = token.span.clone();
+                let expr = self.or()?;
+                if !self.eat(Kind::CloseParen) {
+                    return Err(ParseError::new("unclosed '('", open));
+                }
+                Ok(expr)
+            }
+            kind => {
+                let message = format!("expected a term, found {kind}");
+                Err(ParseError::new(message, token.span.clone()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(query: &str) -> String {
+        Expr::parse(query).unwrap().to_string()
+    }
+
+    fn error(query: &str) -> String {
+        Expr::parse(query).unwrap_err().render(query)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!("(error AND (db OR cache) AND NOT timeout)", parsed("error AND (db OR cache) AND NOT timeout"));
+        // NOT, then AND, then OR
+        assert_eq!("(a OR (b AND NOT c))", parsed("a OR b AND NOT c"));
+        assert_eq!("((a AND b) OR c)", parsed("a AND b OR c"));
+        assert_eq!("(NOT a AND b)", parsed("NOT a AND b"));
+        assert_eq!("NOT (a OR b)", parsed("NOT (a OR b)"));
+        assert_eq!("NOT NOT a", parsed("NOT NOT a"));
+        assert_eq!("(a OR b OR c)", parsed("a OR b OR c"));
+        assert_eq!("((a OR b) AND c)", parsed("(a OR b) AND c"));
+    }
+
+    #[test]
+    fn terms() {
+        assert_eq!("(and OR not)", parsed("and OR not"));
+        assert_eq!("(\"AND\" AND \"two words\")", parsed(r#""AND" AND "two words""#));
+        assert_eq!(r#""say \"hi\"""#, parsed(r#""say \"hi\"""#));
+        assert_eq!("(x AND y)", parsed("(x)AND(y)"));
+        assert_eq!(vec!["error", "db", "cache"], Expr::parse("error AND NOT (db OR cache)").unwrap().terms());
+    }
+
+    #[test]
+    fn eval() {
+        let expr = Expr::parse("error AND (db OR cache) AND NOT timeout").unwrap();
+        let eval = |line: &str| expr.eval(&mut |term| line.contains(term));
+        assert!(eval("error: db connection lost"));
+        assert!(eval("error: cache miss"));
+        assert!(!eval("error: db timeout"));
+        assert!(!eval("warning: db connection lost"));
+    }
+
+    #[test]
+    fn errors_point_at_the_token() {
+        assert_eq!("invalid query: expected a term, found ')'\n  error AND )\n            ^", error("error AND )"));
+        assert_eq!("invalid query: expected AND or OR before 'db'\n  error db\n        ^^", error("error db"));
+        assert_eq!("invalid query: expected a term after AND\n  error AND\n           ^", error("error AND"));
+        assert_eq!("invalid query: unclosed '('\n  a AND (b OR c\n        ^", error("a AND (b OR c"));
+        assert_eq!("invalid query: unmatched ')'\n  a) OR b\n   ^", error("a) OR b"));
+        assert_eq!("invalid query: unterminated quote\n  a OR \"b c\n       ^^^^", error("a OR \"b c"));
+        assert_eq!("invalid query: expected a term, found OR\n  OR a\n  ^^", error("OR a"));
+        assert_eq!("invalid query: empty query\n    \n  ^", error("  "));
+    }
+}
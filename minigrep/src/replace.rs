@@ -0,0 +1,263 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::context::context_ranges;
+use crate::error::Failures;
+use crate::files::{self, Input};
+use crate::matcher::Matcher;
+use crate::{numbered_lines, Config, Error, Match};
+
+// Unchanged lines around each change in --diff output, as in `diff -u`
+const DIFF_CONTEXT: usize = 3;
+
+// The contents of a file, and the lines --replace changes in it
+struct Rewrite<'a> {
+    contents: &'a str,
+    // Each changed line, with its replacement
+    changes: Vec<(Match<'a>, String)>,
+}
+
+impl<'a> Rewrite<'a> {
+    fn new(matcher: &Matcher, contents: &'a str, replacement: &str) -> Rewrite<'a> {
+        let changes = numbered_lines(contents)
+            .filter(|line| matcher.is_match(line.line))
+            .map(|line| (line, matcher.replace(line.line, replacement).0))
+            .filter(|(line, replaced)| line.line != replaced)
+            .collect();
+        Rewrite { contents, changes }
+    }
+
+    // The lines of the file with their line endings, which are kept as they were
+    fn raw_lines(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.contents.split_inclusive('\n').map(|raw| {
+            let line = raw.strip_suffix('\n').map_or(raw, |l| l.strip_suffix('\r').unwrap_or(l));
+            (line, &raw[line.len()..])
+        })
+    }
+
+    fn rewritten(&self) -> String {
+        let mut changes = self.changes.iter().peekable();
+        let mut rewritten = String::with_capacity(self.contents.len());
+
+        for (index, (line, ending)) in self.raw_lines().enumerate() {
+            match changes.next_if(|(m, _)| m.line_number == index + 1) {
+                Some((_, replaced)) => rewritten.push_str(replaced),
+                None => rewritten.push_str(line),
+            }
+            rewritten.push_str(ending);
+        }
+        rewritten
+    }
+
+    // Unified diff from the file to its rewritten version, which `patch -p0`
+    // can apply
+    fn write_diff<W: Write>(&self, out: &mut W, path: &str) -> io::Result<()> {
+        let lines: Vec<(&str, &str)> = self.raw_lines().collect();
+        let changed: Vec<Match> = self.changes.iter().map(|(m, _)| *m).collect();
+        let mut changes = self.changes.iter().peekable();
+        // How many more lines the new file has before the current hunk
+        let mut added: isize = 0;
+
+        writeln!(out, "--- {path}")?;
+        writeln!(out, "+++ {path}")?;
+        for range in context_ranges(&changed, lines.len(), DIFF_CONTEXT, DIFF_CONTEXT) {
+            let mut hunk = Vec::new();
+            // Consecutive changed lines are shown as all removals, then all additions
+            let (mut removed, mut inserted) = (Vec::new(), Vec::new());
+
+            for index in range.clone() {
+                let (line, ending) = lines[index];
+                match changes.next_if(|(m, _)| m.line_number == index + 1) {
+                    Some((_, replaced)) => {
+                        removed.push(format!("-{line}{ending}"));
+                        // A replacement can contain line breaks of its own
+                        let replaced = format!("{replaced}{ending}");
+                        inserted.extend(replaced.split_inclusive('\n').map(|l| format!("+{l}")));
+                    }
+                    None => {
+                        hunk.append(&mut removed);
+                        hunk.append(&mut inserted);
+                        hunk.push(format!(" {line}{ending}"));
+                    }
+                }
+            }
+            hunk.append(&mut removed);
+            hunk.append(&mut inserted);
+
+            let new_len = hunk.iter().filter(|line| !line.starts_with('-')).count();
+            let old_start = range.start + 1;
+            let new_start = old_start as isize + added;
+            writeln!(out, "@@ -{old_start},{} +{new_start},{new_len} @@", range.len())?;
+            for line in hunk {
+                write!(out, "{line}")?;
+                if !line.ends_with('\n') {
+                    write!(out, "\n\\ No newline at end of file\n")?;
+                }
+            }
+            added += new_len as isize - range.len() as isize;
+        }
+        Ok(())
+    }
+}
+
+// --write and --diff: rewrites whole files rather than printing lines. Binary
+// files are left alone, and files must be UTF-8 since they are written back.
+// Returns whether any file changed.
+pub fn rewrite_files<W: Write>(
+    config: &Config,
+    matcher: &Matcher,
+    inputs: &[Input],
+    failures: &Failures,
+    out: &mut W,
+) -> Result<bool, Error> {
+    let mut changed = false;
+
+    for input in inputs {
+        // Standard input is rejected when parsing the arguments
+        let Input::File { path } = input else {
+            continue;
+        };
+        changed |= failures.skip(rewrite_file(config, matcher, path, out))?;
+    }
+
+    Ok(changed)
+}
+
+fn rewrite_file<W: Write>(
+    config: &Config,
+    matcher: &Matcher,
+    path: &Path,
+    out: &mut W,
+) -> Result<bool, Error> {
+    let with_path = |source| Error::Io { path: path.to_path_buf(), source };
+
+    let bytes = fs::read(path).map_err(with_path)?;
+    if files::is_binary(&bytes) {
+        return Ok(false);
+    }
+    let contents = String::from_utf8(bytes).map_err(|_| {
+        Error::Encoding(format!("{}: can't rewrite, not valid UTF-8", path.display()))
+    })?;
+
+    let replacement = config.replace.as_deref().unwrap_or_default();
+    let rewrite = Rewrite::new(matcher, &contents, replacement);
+    if rewrite.changes.is_empty() {
+        return Ok(false);
+    }
+
+    if config.diff {
+        rewrite.write_diff(out, &path.display().to_string())?;
+    }
+    if config.write {
+        files::write_atomically(path, rewrite.rewritten().as_bytes()).map_err(with_path)?;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(pattern: &str) -> Matcher {
+        Matcher::new(&Config { patterns: vec![String::from(pattern)], ..Config::default() }).unwrap()
+    }
+
+    fn diff(rewrite: &Rewrite) -> String {
+        let mut out = Vec::new();
+        rewrite.write_diff(&mut out, "poem.txt").unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn rewritten_keeps_line_endings() {
+        let rewrite = Rewrite::new(&matcher("foo"), "foo\r\nbar\nfoo foo", "baz");
+        assert_eq!("baz\r\nbar\nbaz baz", rewrite.rewritten());
+        assert!(Rewrite::new(&matcher("foo"), "bar\n", "baz").changes.is_empty());
+        // Replacing a match by itself changes nothing
+        assert!(Rewrite::new(&matcher("foo"), "foo\n", "foo").changes.is_empty());
+    }
+
+    #[test]
+    fn diff_hunks() {
+        let contents: String = (1..=20).map(|n| format!("line {n}\n")).collect();
+        // Changes 7 lines apart share a hunk, further apart they don't
+        let m = Matcher::new(&Config {
+            patterns: vec![String::from("line 2"), String::from("line 9"), String::from("line 19")],
+            line_regexp: true,
+            ..Config::default()
+        })
+        .unwrap();
+        let rewrite = Rewrite::new(&m, &contents, "changed\nand added");
+        assert_eq!(
+            "\
+--- poem.txt
++++ poem.txt
+@@ -1,12 +1,14 @@
+ line 1
+-line 2
++changed
++and added
+ line 3
+ line 4
+ line 5
+ line 6
+ line 7
+ line 8
+-line 9
++changed
++and added
+ line 10
+ line 11
+ line 12
+@@ -16,5 +18,6 @@
+ line 16
+ line 17
+ line 18
+-line 19
++changed
++and added
+ line 20
+",
+            diff(&rewrite)
+        );
+    }
+
+    #[test]
+    fn consecutive_changes() {
+        let rewrite = Rewrite::new(&matcher("x"), "x1\nx2\nz\n", "y");
+        assert_eq!("--- poem.txt\n+++ poem.txt\n@@ -1,3 +1,3 @@\n-x1\n-x2\n+y1\n+y2\n z\n", diff(&rewrite));
+    }
+
+    #[test]
+    fn diff_without_final_newline() {
+        let rewrite = Rewrite::new(&matcher("b"), "a\nb", "c");
+        assert_eq!(
+            "--- poem.txt\n+++ poem.txt\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n\\ No newline at end of file\n",
+            diff(&rewrite)
+        );
+    }
+
+    #[test]
+    fn files_are_rewritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("poem.txt");
+        fs::write(&path, "I'm nobody!\nWho are you?\n").unwrap();
+        let config = Config {
+            patterns: vec![String::from("nobody")],
+            replace: Some(String::from("somebody")),
+            write: true,
+            ..Config::default()
+        };
+        let inputs = [Input::File { path: path.clone() }];
+        let (matcher, failures) = (Matcher::new(&config).unwrap(), Failures::default());
+
+        let mut out = Vec::new();
+        assert!(rewrite_files(&config, &matcher, &inputs, &failures, &mut out).unwrap());
+        assert!(out.is_empty());
+        assert_eq!("I'm somebody!\nWho are you?\n", fs::read_to_string(&path).unwrap());
+
+        // Nothing left to change
+        assert!(!rewrite_files(&config, &matcher, &inputs, &failures, &mut out).unwrap());
+    }
+}
@@ -0,0 +1,313 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::files::Input;
+use crate::matcher::Matcher;
+use crate::sink::Sink;
+use crate::{cli, input_error, multiline, open_input, stream, Config, Error};
+
+/// minigrep as a library: a compiled set of patterns and the options that
+/// decide which lines are selected, with a `Sink` deciding what happens to
+/// them. `run` searches through a `Searcher` as well.
+///
+/// Input is read like on the command line: line by line, with invalid UTF-8
+/// replaced, and binary input reported with `Sink::binary_match`.
+///
+/// ```
+/// use std::io;
+/// use minigrep::{Match, Searcher, Sink, Submatch};
+///
+/// // Collects the numbers of the selected lines
+/// struct LineNumbers(Vec<usize>);
+///
+/// impl Sink for LineNumbers {
+///     fn matched(&mut self, line: &Match, _: &[Submatch]) -> io::Result<()> {
+///         self.0.push(line.line_number);
+///         Ok(())
+///     }
+/// }
+///
+/// let searcher = Searcher::builder().pattern("error").ignore_case(true).build()?;
+/// let mut lines = LineNumbers(Vec::new());
+/// let log = "ERROR db down\nINFO retrying\nerror again\n";
+/// assert_eq!(2, searcher.search_reader(log.as_bytes(), &mut lines)?);
+/// assert_eq!(vec![1, 3], lines.0);
+/// # Ok::<(), minigrep::Error>(())
+/// ```
+pub struct Searcher {
+    config: Config,
+    matcher: Matcher,
+}
+
+// What errors reading a `search_reader` reader are about
+const READER_PATH: &str = "(reader)";
+
+impl Searcher {
+    pub fn builder() -> SearcherBuilder {
+        SearcherBuilder { config: Config::default() }
+    }
+
+    // `config` is checked and its patterns compiled, whether it comes from
+    // the command line, a `SearcherBuilder` or was filled in by hand
+    pub(crate) fn new(config: Config) -> Result<Searcher, Error> {
+        let config = cli::prepare(config)?;
+        let matcher = Matcher::new(&config).map_err(Error::Pattern)?;
+        Ok(Searcher { config, matcher })
+    }
+
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub(crate) fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+
+    /// Searches the file at `path`, telling `sink` about it with `Sink::begin`
+    /// first. Returns the number of selected lines. An error is an
+    /// `Error::Io` about `path`, unless it is `Error::Output` from the sink.
+    pub fn search_path<P: AsRef<Path>, S: Sink>(
+        &self,
+        path: P,
+        sink: &mut S,
+    ) -> Result<usize, Error> {
+        self.search_input(&Input::File { path: path.as_ref().to_path_buf() }, sink)
+    }
+
+    /// Searches everything `reader` returns, which has no path to pass to
+    /// `Sink::begin`. Returns the number of selected lines, and errors like
+    /// `search_path` does, with `(reader)` as the path.
+    pub fn search_reader<R: Read, S: Sink>(&self, reader: R, sink: &mut S) -> Result<usize, Error> {
+        open_input(&self.config, BufReader::new(reader))
+            .and_then(|reader| self.search_lines(reader, sink))
+            .map_err(|e| input_error(Path::new(READER_PATH), e))
+    }
+
+    // Opens `input`, decoded by `open_input`, and searches it. Errors are
+    // about the path that is passed to `Sink::begin`.
+    pub(crate) fn search_input<S: Sink>(
+        &self,
+        input: &Input,
+        sink: &mut S,
+    ) -> Result<usize, Error> {
+        let mut search = |reader, path: &Path| {
+            sink.begin(path);
+            self.search_lines(reader, sink)
+        };
+        let path = match input {
+            Input::Stdin => {
+                let path = Path::new("(standard input)");
+                let result = open_input(&self.config, io::stdin().lock())
+                    .and_then(|reader| search(reader, path));
+                return result.map_err(|e| input_error(path, e));
+            }
+            Input::File { path } => path,
+        };
+
+        let file = File::open(path).map_err(|source| Error::Io { path: path.clone(), source })?;
+        open_input(&self.config, BufReader::new(file))
+            .and_then(|reader| search(reader, path))
+            .map_err(|e| input_error(path, e))
+    }
+
+    // Searches one input and tells `sink` what was found, ending with
+    // `Sink::end`. Returns the number of selected lines.
+    pub(crate) fn search_lines<R: BufRead, S: Sink>(
+        &self,
+        reader: R,
+        sink: &mut S,
+    ) -> io::Result<usize> {
+        let selected = if self.config.multiline {
+            multiline::search_reader(&self.config, &self.matcher, reader, sink)?
+        } else {
+            stream::search_reader(&self.config, &self.matcher, reader, sink)?
+        };
+        sink.end(selected)?;
+        Ok(selected)
+    }
+}
+
+/// The options of a `Searcher`, which work like the command line options of
+/// the same name. A line is selected when any of the patterns matches it.
+pub struct SearcherBuilder {
+    config: Config,
+}
+
+impl SearcherBuilder {
+    pub fn pattern(mut self, pattern: impl Into<String>) -> SearcherBuilder {
+        self.config.patterns.push(pattern.into());
+        self
+    }
+
+    pub fn regex(mut self, yes: bool) -> SearcherBuilder {
+        self.config.regex = yes;
+        self
+    }
+
+    /// Patterns are queries like `error AND NOT timeout`
+    pub fn boolean(mut self, yes: bool) -> SearcherBuilder {
+        self.config.boolean = yes;
+        self
+    }
+
+    pub fn fuzzy(mut self, max_distance: usize) -> SearcherBuilder {
+        self.config.fuzzy = Some(max_distance);
+        self
+    }
+
+    pub fn word_regexp(mut self, yes: bool) -> SearcherBuilder {
+        self.config.word_regexp = yes;
+        self
+    }
+
+    pub fn line_regexp(mut self, yes: bool) -> SearcherBuilder {
+        self.config.line_regexp = yes;
+        self
+    }
+
+    pub fn multiline(mut self, yes: bool) -> SearcherBuilder {
+        self.config.multiline = yes;
+        self
+    }
+
+    pub fn ignore_case(mut self, yes: bool) -> SearcherBuilder {
+        self.config.ignore_case = yes;
+        self.config.smart_case = false;
+        self
+    }
+
+    /// Ignore case unless a pattern has an uppercase letter
+    pub fn smart_case(mut self, yes: bool) -> SearcherBuilder {
+        self.config.smart_case = yes;
+        self
+    }
+
+    pub fn invert_match(mut self, yes: bool) -> SearcherBuilder {
+        self.config.invert_match = yes;
+        self
+    }
+
+    pub fn before_context(mut self, lines: usize) -> SearcherBuilder {
+        self.config.before_context = lines;
+        self
+    }
+
+    pub fn after_context(mut self, lines: usize) -> SearcherBuilder {
+        self.config.after_context = lines;
+        self
+    }
+
+    pub fn context(self, lines: usize) -> SearcherBuilder {
+        self.before_context(lines).after_context(lines)
+    }
+
+    /// Stop reading an input after this many selected lines
+    pub fn max_count(mut self, lines: usize) -> SearcherBuilder {
+        self.config.max_count = Some(lines);
+        self
+    }
+
+    /// Options that don't go together are an `Error::Args`, patterns that
+    /// don't compile an `Error::Pattern`
+    pub fn build(self) -> Result<Searcher, Error> {
+        Searcher::new(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Match, Submatch};
+
+    // Every event as a line of text
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl Sink for Events {
+        fn begin(&mut self, path: &Path) {
+            self.0.push(format!("begin {}", path.file_name().unwrap().to_string_lossy()));
+        }
+
+        fn matched(&mut self, line: &Match, submatches: &[Submatch]) -> io::Result<()> {
+            let spans: Vec<_> = submatches.iter().map(Submatch::span).collect();
+            self.0.push(format!("match {}:{} {spans:?}", line.line_number, line.line));
+            Ok(())
+        }
+
+        fn context(&mut self, line: &Match) -> io::Result<()> {
+            self.0.push(format!("context {}:{}", line.line_number, line.line));
+            Ok(())
+        }
+
+        fn context_break(&mut self) -> io::Result<()> {
+            self.0.push(String::from("--"));
+            Ok(())
+        }
+
+        fn end(&mut self, selected: usize) -> io::Result<()> {
+            self.0.push(format!("end {selected}"));
+            Ok(())
+        }
+    }
+
+    const POEM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/poem.txt");
+
+    #[test]
+    fn sink_gets_matches_and_context() {
+        let searcher = Searcher::builder().pattern("Nobody").smart_case(false).context(1);
+        let mut events = Events::default();
+        assert_eq!(0, searcher.build().unwrap().search_path(POEM, &mut events).unwrap());
+        assert_eq!(vec!["begin poem.txt", "end 0"], events.0);
+
+        let searcher =
+            Searcher::builder().pattern("nobody").pattern("body!").context(1).max_count(2);
+        let mut events = Events::default();
+        assert_eq!(2, searcher.build().unwrap().search_path(POEM, &mut events).unwrap());
+        assert_eq!(
+            vec![
+                "begin poem.txt",
+                "--",
+                "match 1:I'm nobody! Who are you? [4..10]",
+                "match 2:Are you nobody, too? [8..14]",
+                "context 3:Then there's a pair of us - don't tell!",
+                "end 2",
+            ],
+            events.0
+        );
+    }
+
+    #[test]
+    fn reader_with_regex_and_smart_case() {
+        let contents = "INFO start\nERROR db\ninfo end\n";
+        let searcher = Searcher::builder().pattern(r"^info\b").regex(true).smart_case(true);
+        let mut events = Events::default();
+        let selected = searcher.build().unwrap().search_reader(contents.as_bytes(), &mut events);
+        assert_eq!(2, selected.unwrap());
+        assert_eq!(vec!["match 1:INFO start [0..4]", "match 3:info end [0..4]", "end 2"], events.0);
+    }
+
+    #[test]
+    fn invalid_options_and_patterns() {
+        let searcher = Searcher::builder().pattern("(unclosed").regex(true).build();
+        assert!(matches!(searcher, Err(Error::Pattern(_))));
+        let searcher = Searcher::builder().pattern("a").regex(true).fuzzy(1).build();
+        assert!(matches!(searcher, Err(Error::Args(_))));
+        let searcher = Searcher::builder().pattern("a AND").boolean(true).build();
+        assert!(matches!(searcher, Err(Error::Pattern(_))));
+
+        let searcher = Searcher::builder().pattern("a").build().unwrap();
+        let result = searcher.search_path("does/not/exist.txt", &mut Events::default());
+        assert!(matches!(result, Err(Error::Io { .. })));
+        let result = searcher.search_reader(Unreadable, &mut Events::default());
+        assert_eq!("(reader): gone", result.unwrap_err().to_string());
+    }
+
+    struct Unreadable;
+
+    impl Read for Unreadable {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("gone"))
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::io;
+use std::path::Path;
+
+use crate::json::Submatch;
+use crate::Match;
+
+/// Receives what a search finds, in line order. Printing the
+/// results is one sink, counting --aggregate values another, and a program
+/// using the library can collect the lines into its own structures. Only
+/// `matched` has to be implemented, the other events are ignored by default.
+///
+/// Returning an error stops the search and passes the error on.
+pub trait Sink {
+    /// Before an input is searched, with the path to print for it, which is
+    /// `(standard input)` for stdin. The events that follow are about it.
+    /// `Searcher::search_reader` has no path and doesn't call it.
+    fn begin(&mut self, _path: &Path) {}
+
+    /// A selected line, with the parts of it that matched. They are empty for
+    /// lines selected by `invert_match`, and when `wants_submatches` is false.
+    fn matched(&mut self, line: &Match, submatches: &[Submatch]) -> io::Result<()>;
+
+    /// A line of leading or trailing context around the selected lines
+    fn context(&mut self, _line: &Match) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Before each group of selected lines and their context, when context
+    /// was asked for. Groups that touch are merged into one.
+    fn context_break(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The input turned out to be binary and has a selected line. No lines
+    /// follow, but `end` still counts the selected ones.
+    fn binary_match(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A line that couldn't be searched, like one --log-format can't parse
+    fn invalid(&mut self, _line_number: usize, _reason: &str) {}
+
+    /// The whole input is searched, with the number of selected lines
+    fn end(&mut self, _selected: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Finding the submatches of a line takes a second pass over it, which is
+    /// skipped when the sink doesn't use them
+    fn wants_submatches(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use crate::files;
+use crate::json::Submatch;
+use crate::matcher::Matcher;
+use crate::sink::Sink;
+use crate::{extract, structured, Config, Match};
+
+// A line kept around as possible leading context for a later match
+struct Buffered {
+    line_number: usize,
+    byte_offset: usize,
+    line: String,
+}
+
+impl Buffered {
+    fn as_match(&self) -> Match<'_> {
+        Match {
+            line_number: self.line_number,
+            byte_offset: self.byte_offset,
+            line: &self.line,
+        }
+    }
+}
+
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    match line.strip_suffix(b"\n") {
+        Some(line) => line.strip_suffix(b"\r").unwrap_or(line),
+        None => line,
+    }
+}
+
+// Searches `reader` line by line and prints the selected lines as they are
+// found. Only the current line and at most `before_context` earlier lines are
+// held in memory, so input of any size can be searched. Returns the number of
+// selected lines, which is all the output modes that don't print lines need.
+//
+// Invalid UTF-8 is replaced rather than an error. Input with NUL bytes is
+// binary, like in grep: instead of its lines only "Binary file ... matches" is
+// printed, once.
+pub fn search_reader<R: BufRead, S: Sink>(
+    config: &Config,
+    matcher: &Matcher,
+    mut reader: R,
+    sink: &mut S,
+) -> io::Result<usize> {
+    // Listing a file, or knowing that anything matched, needs only one line
+    let stop_at_first = config.files_with_matches || config.files_without_match || config.quiet;
+    let print_lines = !config.count && !stop_at_first;
+    let max_count = config.max_count.unwrap_or(usize::MAX);
+    let has_context = config.before_context > 0 || config.after_context > 0;
+    // Inverted lines contain no matches
+    let find_spans = sink.wants_submatches() && !config.invert_match;
+
+    let mut before: VecDeque<Buffered> = VecDeque::with_capacity(config.before_context);
+    let mut after_remaining = 0;
+    let mut last_printed = None;
+    let mut selected = 0;
+
+    // A NUL byte further into the input still makes it binary from there on
+    let mut binary = files::is_binary(reader.fill_buf()?);
+    let mut buffer = Vec::new();
+    let mut line_number = 0;
+    let mut byte_offset = 0;
+
+    loop {
+        // After -m lines only the trailing context of the last one is printed
+        let reached_max = selected >= max_count;
+        if reached_max && after_remaining == 0 {
+            break;
+        }
+
+        buffer.clear();
+        let read = reader.read_until(b'\n', &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        line_number += 1;
+        binary = binary || buffer.contains(&0);
+
+        let text = String::from_utf8_lossy(trim_line_ending(&buffer));
+        let line = Match { line_number, byte_offset, line: &text };
+        byte_offset += read;
+
+        let selects = match matcher.try_match(line.line) {
+            Ok(matched) => matched != config.invert_match,
+            // Lines that can't be parsed for --log-format are never selected
+            Err(reason) => {
+                sink.invalid(line_number, &reason);
+                false
+            }
+        };
+        if selects {
+            if reached_max {
+                break;
+            }
+            selected += 1;
+            if stop_at_first {
+                break;
+            }
+            if !print_lines {
+                continue;
+            }
+            // JSON can carry any line, so it is written like any other
+            if binary && !config.json {
+                sink.binary_match()?;
+                break;
+            }
+
+            if has_context {
+                let first = before.front().map_or(line_number, |b| b.line_number);
+                if last_printed != Some(first - 1) {
+                    sink.context_break()?;
+                }
+            }
+            for buffered in before.drain(..) {
+                sink.context(&buffered.as_match())?;
+            }
+            if config.extract.is_some() || config.field.is_some() {
+                // Lines without the value are still selected, but not printed
+                if let Some(value) = extract::value(config, matcher, line.line) {
+                    sink.matched(&Match { line: &value, ..line }, &[])?;
+                }
+            } else if !config.select.is_empty() {
+                let format = config.log_format.unwrap_or_default();
+                // Parsed again, the matcher doesn't keep the record
+                let selected = structured::Record::parse(format, line.line)
+                    .map(|record| structured::select(&record, &config.select))
+                    .unwrap_or_default();
+                sink.matched(&Match { line: &selected, ..line }, &[])?;
+            } else if let Some(replacement) = &config.replace {
+                let (replaced, spans) = matcher.replace(line.line, replacement);
+                let submatches: Vec<Submatch> = spans.iter().map(Submatch::from).collect();
+                sink.matched(&Match { line: &replaced, ..line }, &submatches)?;
+            } else {
+                let submatches =
+                    if find_spans { matcher.find_submatches(line.line) } else { Vec::new() };
+                sink.matched(&line, &submatches)?;
+            }
+
+            last_printed = Some(line_number);
+            after_remaining = config.after_context;
+        } else if !print_lines {
+            continue;
+        } else if after_remaining > 0 {
+            sink.context(&line)?;
+            last_printed = Some(line_number);
+            after_remaining -= 1;
+        } else if config.before_context > 0 {
+            if before.len() == config.before_context {
+                before.pop_front();
+            }
+            before.push_back(Buffered {
+                line_number,
+                byte_offset: line.byte_offset,
+                line: line.line.to_string(),
+            });
+        }
+    }
+
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    use crate::color::ColorChoice;
+    use crate::printer::Printer;
+
+    const CONTENTS: &str = "a\nfoo\nb\nc\nd\ne\nfoo\nf\n";
+
+    fn output(config: Config) -> (String, usize) {
+        let matcher = Matcher::new(&config).unwrap();
+        let mut printer = Printer::new(&config, false, Vec::new());
+        let selected = search_reader(&config, &matcher, CONTENTS.as_bytes(), &mut printer).unwrap();
+        (String::from_utf8(printer.into_inner()).unwrap(), selected)
+    }
+
+    fn query(query: &str) -> Config {
+        Config { patterns: vec![String::from(query)], ..Config::default() }
+    }
+
+    #[test]
+    fn plain_matches() {
+        let config = Config { line_number: true, byte_offset: true, ..query("foo") };
+        assert_eq!((String::from("2:2:foo\n7:14:foo\n"), 2), output(config));
+    }
+
+    #[test]
+    fn context_groups() {
+        let config = Config { line_number: true, before_context: 1, after_context: 1, ..query("foo") };
+        assert_eq!(String::from("1-a\n2:foo\n3-b\n--\n6-e\n7:foo\n8-f\n"), output(config).0);
+    }
+
+    #[test]
+    fn overlapping_context_is_merged() {
+        let config = Config { before_context: 2, after_context: 3, ..query("foo") };
+        assert_eq!(String::from(CONTENTS), output(config).0);
+    }
+
+    #[test]
+    fn inverted_count() {
+        let config = Config { invert_match: true, count: true, ..query("foo") };
+        assert_eq!((String::new(), 6), output(config));
+    }
+
+    #[test]
+    fn files_with_matches_stops_early() {
+        let config = Config { files_with_matches: true, ..query("foo") };
+        assert_eq!((String::new(), 1), output(config));
+    }
+
+    #[test]
+    fn files_without_match_and_quiet_stop_early() {
+        let config = Config { files_without_match: true, ..query("foo") };
+        assert_eq!((String::new(), 1), output(config));
+        let config = Config { quiet: true, count: true, ..query("foo") };
+        assert_eq!((String::new(), 1), output(config));
+    }
+
+    #[test]
+    fn max_count() {
+        let config = Config { line_number: true, max_count: Some(1), ..query("foo") };
+        assert_eq!((String::from("2:foo\n"), 1), output(config));
+
+        // Trailing context is still printed, up to the next selected line
+        let config = Config { max_count: Some(1), after_context: 9, ..query("foo") };
+        assert_eq!(String::from("foo\nb\nc\nd\ne\n"), output(config).0);
+
+        let config = Config { max_count: Some(3), invert_match: true, count: true, ..query("foo") };
+        assert_eq!((String::new(), 3), output(config));
+        assert_eq!((String::new(), 0), output(Config { max_count: Some(0), ..query("foo") }));
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let config = query("caf");
+        let matcher = Matcher::new(&config).unwrap();
+        let mut printer = Printer::new(&config, false, Vec::new());
+        search_reader(&config, &matcher, &b"caf\xe9\nbar\n"[..], &mut printer).unwrap();
+        assert_eq!("caf\u{fffd}\n", String::from_utf8(printer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn binary_matches() {
+        let search = |config: Config, contents: &[u8]| {
+            let matcher = Matcher::new(&config).unwrap();
+            let mut printer = Printer::new(&config, false, Vec::new());
+            printer.begin(Path::new("app.bin"));
+            // A small buffer, so the NUL byte isn't seen up front when it comes later
+            let reader = io::BufReader::with_capacity(4, contents);
+            let selected = search_reader(&config, &matcher, reader, &mut printer).unwrap();
+            (String::from_utf8(printer.into_inner()).unwrap(), selected)
+        };
+
+        let contents = b"EL\0\nfoo\nfoo\n";
+        assert_eq!((String::from("Binary file app.bin matches\n"), 1), search(query("foo"), contents));
+        assert_eq!((String::new(), 0), search(query("bar"), contents));
+        // Counting works the same as for text
+        assert_eq!((String::new(), 2), search(Config { count: true, ..query("foo") }, contents));
+
+        // Lines before a late NUL byte are still printed as text
+        let contents = b"foo\nfoo\0\nfoo\n";
+        assert_eq!((String::from("foo\nBinary file app.bin matches\n"), 2), search(query("foo"), contents));
+    }
+
+    #[test]
+    fn replaced_lines() {
+        let config = Config {
+            replace: Some(String::from("bar")),
+            line_number: true,
+            after_context: 1,
+            ..query("foo")
+        };
+        assert_eq!("2:bar\n3-b\n--\n7:bar\n8-f\n", output(config).0);
+    }
+
+    #[test]
+    fn log_fields() {
+        let lines = "\
+{\"level\":\"error\",\"msg\":\"db down\"}
+level=info msg=started
+not a log line
+level=error msg=\"cache miss\" user=7
+";
+        let config = Config {
+            log_format: Some(crate::LogFormat::Auto),
+            select: vec![String::from("msg"), String::from("user")],
+            line_number: true,
+            ..query("level=error")
+        };
+        let matcher = Matcher::new(&config).unwrap();
+        let mut printer = Printer::new(&config, false, Vec::new());
+        let selected = search_reader(&config, &matcher, lines.as_bytes(), &mut printer).unwrap();
+        assert_eq!(2, selected);
+        let out = String::from_utf8(printer.into_inner()).unwrap();
+        assert_eq!("1:msg=\"db down\"\n4:msg=\"cache miss\" user=7\n", out);
+
+        // Lines that can't be parsed aren't selected by -v either
+        let config = Config { invert_match: true, select: Vec::new(), ..config };
+        let matcher = Matcher::new(&config).unwrap();
+        let mut printer = Printer::new(&config, false, Vec::new());
+        assert_eq!(1, search_reader(&config, &matcher, lines.as_bytes(), &mut printer).unwrap());
+    }
+
+    #[test]
+    fn colored_output() {
+        let config = Config {
+            color: ColorChoice::Always,
+            line_number: true,
+            after_context: 1,
+            ..query("fo")
+        };
+        let (output, _) = output(config);
+        assert!(output.starts_with(
+            "\x1b[32m2\x1b[m\x1b[36m:\x1b[m\x1b[01;31mfo\x1b[mo\n\x1b[32m3\x1b[m\x1b[36m-\x1b[mb\n"
+        ));
+        assert!(output.contains("\x1b[36m--\x1b[m\n"));
+    }
+
+    #[test]
+    fn json_records() {
+        let config = Config { json: true, after_context: 1, ..query("o") };
+        let matcher = Matcher::new(&config).unwrap();
+        let mut printer = Printer::new(&config, false, Vec::new());
+        printer.begin(Path::new("nothing.txt"));
+        let selected = search_reader(&config, &matcher, "abc\n".as_bytes(), &mut printer).unwrap();
+        printer.end(selected).unwrap();
+        printer.begin(Path::new("poem.txt"));
+        let selected = search_reader(&config, &matcher, "foo\nbar\n".as_bytes(), &mut printer).unwrap();
+        printer.end(selected).unwrap();
+
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            vec![
+                r#"{"type":"begin","path":"poem.txt"}"#,
+                r#"{"type":"match","path":"poem.txt","line_number":1,"byte_offset":0,"line":"foo","submatches":[{"start":1,"end":2},{"start":2,"end":3}]}"#,
+                r#"{"type":"context","path":"poem.txt","line_number":2,"byte_offset":4,"line":"bar"}"#,
+                r#"{"type":"end","path":"poem.txt","matched_lines":1}"#,
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn crlf_and_missing_final_newline() {
+        let config = query("o");
+        let matcher = Matcher::new(&config).unwrap();
+        let mut printer = Printer::new(&config, false, Vec::new());
+        search_reader(&config, &matcher, "one\r\ntwo".as_bytes(), &mut printer).unwrap();
+        assert_eq!("one\ntwo\n", String::from_utf8(printer.into_inner()).unwrap());
+    }
+}
@@ -0,0 +1,314 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use regex::{Regex, RegexBuilder};
+use serde_json::Value;
+
+use crate::case;
+
+// --log-format: each line is parsed as a JSON object or as logfmt
+// (`level=error msg="no route" status=404`), and the patterns are conditions
+// on its fields instead of text to find:
+//
+//   level=error           the field is exactly `error`
+//   level!=debug          the field is there and isn't `debug`
+//   .request.path~/api/   the field matches the regex, slashes around it optional
+//   msg!~timeout          the field is there and doesn't match the regex
+//
+// A field is a path of keys separated by dots, a leading dot is optional.
+// In JSON each key goes one object (or array index) deeper, and failing that
+// the whole path is tried as a single key, which is the only way for logfmt.
+// Strings are compared as they are, other JSON values as JSON text, so
+// `status=500` finds the number 500. A missing field matches no condition.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    Json,
+    Logfmt,
+    /// JSON for lines that start with `{`, logfmt for the others
+    #[default]
+    Auto,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s {
+            "json" => Ok(LogFormat::Json),
+            "logfmt" => Ok(LogFormat::Logfmt),
+            "auto" => Ok(LogFormat::Auto),
+            _ => Err(format!("invalid log format '{s}', expected json, logfmt or auto")),
+        }
+    }
+}
+
+// A parsed line
+pub enum Record {
+    Json(Value),
+    Logfmt(Vec<(String, String)>),
+}
+
+impl Record {
+    // Why the line can't be parsed is what `--report-invalid` prints
+    pub fn parse(format: LogFormat, line: &str) -> Result<Record, String> {
+        let json = match format {
+            LogFormat::Json => true,
+            LogFormat::Logfmt => false,
+            LogFormat::Auto => line.trim_start().starts_with('{'),
+        };
+        if json {
+            match serde_json::from_str(line) {
+                Ok(value @ Value::Object(_)) => Ok(Record::Json(value)),
+                Ok(_) => Err(String::from("not a JSON object")),
+                Err(e) => Err(format!("not valid JSON: {e}")),
+            }
+        } else {
+            parse_logfmt(line).map(Record::Logfmt)
+        }
+    }
+
+    pub fn get(&self, field: &str) -> Option<Cow<'_, str>> {
+        let field = field.strip_prefix('.').unwrap_or(field);
+        match self {
+            Record::Json(value) => {
+                let nested = field.split('.').try_fold(value, |value, key| match value {
+                    Value::Object(object) => object.get(key),
+                    Value::Array(array) => array.get(key.parse::<usize>().ok()?),
+                    _ => None,
+                });
+                nested.or_else(|| value.get(field)).map(|value| match value {
+                    Value::String(s) => Cow::Borrowed(s.as_str()),
+                    value => Cow::Owned(value.to_string()),
+                })
+            }
+            Record::Logfmt(pairs) => {
+                pairs.iter().find(|(key, _)| key == field).map(|(_, value)| value.into())
+            }
+        }
+    }
+}
+
+// `key=value` pairs separated by spaces, where a value with spaces is quoted
+// and a key without a value is true. A line has to have at least one
+// `key=value` to count as logfmt, otherwise any text would.
+fn parse_logfmt(line: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    let mut has_value = false;
+    let mut rest = line.trim_start();
+
+    while !rest.is_empty() {
+        let key_end = rest.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        if key.is_empty() || key.contains('"') {
+            return Err(String::from("not logfmt, expected a key"));
+        }
+        rest = &rest[key_end..];
+
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                has_value = true;
+                let (value, after) = match after.strip_prefix('"') {
+                    Some(quoted) => unquote(quoted)?,
+                    None => {
+                        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                        (after[..end].to_string(), &after[end..])
+                    }
+                };
+                rest = after;
+                value
+            }
+            None => String::from("true"),
+        };
+        pairs.push((key.to_string(), value));
+
+        let trimmed = rest.trim_start();
+        if trimmed.len() == rest.len() && !rest.is_empty() {
+            return Err(String::from("not logfmt, expected a space after a quoted value"));
+        }
+        rest = trimmed;
+    }
+
+    if !has_value {
+        return Err(String::from("not logfmt, no key=value pairs"));
+    }
+    Ok(pairs)
+}
+
+// The value of a quoted string that `quoted` starts right after the opening
+// quote of, and the rest of the line after the closing one
+fn unquote(quoted: &str) -> Result<(String, &str), String> {
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((at, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &quoted[at + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, c)) => value.push(c),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    Err(String::from("not logfmt, unterminated quote"))
+}
+
+enum Test {
+    Equals(String),
+    Matches(Regex),
+}
+
+// A condition on one field of a record
+pub struct Condition {
+    field: String,
+    negated: bool,
+    test: Test,
+    ignore_case: bool,
+}
+
+impl Condition {
+    pub fn parse(condition: &str, ignore_case: bool) -> Result<Condition, String> {
+        let invalid = || {
+            format!(
+                "invalid field condition '{condition}', expected FIELD=VALUE, FIELD!=VALUE, \
+                 FIELD~REGEX or FIELD!~REGEX"
+            )
+        };
+        let at = condition.find(['=', '~']).ok_or_else(invalid)?;
+        let (field, negated) = match condition[..at].strip_suffix('!') {
+            Some(field) => (field, true),
+            None => (&condition[..at], false),
+        };
+        if field.strip_prefix('.').unwrap_or(field).is_empty() {
+            return Err(invalid());
+        }
+
+        let value = &condition[at + 1..];
+        let test = if condition[at..].starts_with('~') {
+            let pattern = value
+                .strip_prefix('/')
+                .and_then(|v| v.strip_suffix('/'))
+                .unwrap_or(value);
+            let regex = RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .map_err(|e| format!("invalid regex '{pattern}' in '{condition}': {e}"))?;
+            Test::Matches(regex)
+        } else if ignore_case {
+            Test::Equals(case::fold(value))
+        } else {
+            Test::Equals(value.to_string())
+        };
+
+        Ok(Condition { field: field.to_string(), negated, test, ignore_case })
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        let Some(value) = record.get(&self.field) else {
+            return false;
+        };
+        let found = match &self.test {
+            Test::Equals(expected) if self.ignore_case => case::fold(&value) == *expected,
+            Test::Equals(expected) => *value == *expected,
+            Test::Matches(regex) => regex.is_match(&value),
+        };
+        found != self.negated
+    }
+}
+
+// --select: the given fields of `record` as logfmt, in the order they were
+// asked for. Fields the record doesn't have are left out.
+pub fn select(record: &Record, fields: &[String]) -> String {
+    let mut selected = String::new();
+    for field in fields {
+        let Some(value) = record.get(field) else {
+            continue;
+        };
+        if !selected.is_empty() {
+            selected.push(' ');
+        }
+        let key = field.strip_prefix('.').unwrap_or(field);
+        let needs_quotes = |c: char| c.is_whitespace() || c == '"' || c == '=';
+        let plain = !value.is_empty() && !value.contains(needs_quotes);
+        if plain {
+            let _ = write!(selected, "{key}={value}");
+        } else {
+            let _ = write!(selected, "{key}={value:?}");
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(condition: &str, line: &str) -> bool {
+        let record = Record::parse(LogFormat::Auto, line).unwrap();
+        Condition::parse(condition, false).unwrap().matches(&record)
+    }
+
+    const JSON: &str = r#"{"level":"error","status":500,"request":{"path":"/api/users","tags":["a","b"]},"k.8s":"pod"}"#;
+    const LOGFMT: &str = r#"level=error status=500 request.path=/api/users msg="no route, try again" retry"#;
+
+    #[test]
+    fn conditions() {
+        for line in [JSON, LOGFMT] {
+            assert!(matches("level=error", line));
+            assert!(matches(".level=error", line));
+            assert!(!matches("level=erro", line));
+            assert!(matches("level!=debug", line));
+            assert!(matches("status=500", line));
+            assert!(matches(".request.path~/api/", line));
+            assert!(matches("request.path~^/api/users$", line));
+            assert!(!matches("request.path!~api", line));
+            // A missing field matches nothing
+            assert!(!matches("user=bob", line));
+            assert!(!matches("user!=bob", line));
+        }
+        assert!(matches("request.tags.1=b", JSON));
+        assert!(matches("k.8s=pod", JSON));
+        assert!(matches("msg~route", LOGFMT));
+        assert!(matches("retry=true", LOGFMT));
+
+        let record = Record::parse(LogFormat::Json, JSON).unwrap();
+        // Only values ignore case, not field names
+        assert!(!Condition::parse("LEVEL=ERROR", true).unwrap().matches(&record));
+        assert!(Condition::parse("level=ERROR", true).unwrap().matches(&record));
+    }
+
+    #[test]
+    fn invalid_conditions() {
+        assert!(Condition::parse("level", false).is_err());
+        assert!(Condition::parse("=error", false).is_err());
+        assert!(Condition::parse(".!=error", false).is_err());
+        assert!(Condition::parse("msg~(", false).is_err());
+        // The value can have any of the operators in it
+        assert!(Condition::parse("query=a=b~c", false).is_ok());
+    }
+
+    #[test]
+    fn unparsable_lines() {
+        assert!(Record::parse(LogFormat::Auto, "plain text line").is_err());
+        assert!(Record::parse(LogFormat::Auto, "{\"level\":").is_err());
+        assert!(Record::parse(LogFormat::Json, "[1, 2]").is_err());
+        assert!(Record::parse(LogFormat::Json, "level=error").is_err());
+        assert!(Record::parse(LogFormat::Logfmt, "msg=\"unterminated").is_err());
+        assert!(Record::parse(LogFormat::Logfmt, "msg=\"a\"b=c").is_err());
+        assert!(Record::parse(LogFormat::Logfmt, "").is_err());
+    }
+
+    #[test]
+    fn selected_fields() {
+        let record = Record::parse(LogFormat::Auto, LOGFMT).unwrap();
+        let fields = ["level", "msg", "user", ".status"].map(String::from);
+        assert_eq!(r#"level=error msg="no route, try again" status=500"#, select(&record, &fields));
+
+        let record = Record::parse(LogFormat::Auto, JSON).unwrap();
+        let fields = ["request.path", "request.tags"].map(String::from);
+        assert_eq!(r#"request.path=/api/users request.tags="[\"a\",\"b\"]""#, select(&record, &fields));
+    }
+}
@@ -0,0 +1,158 @@
+use actix_web::{delete, get, patch, post, put, web, HttpResponse, Responder, Result};
+use actix_web::http::header;
+use crate::todo::{NewTodo, TodoChanges, TodoItem};
+use crate::TodoAppState;
+
+// The JSON API. Items are addressed by their id, which is part of every item
+// that is returned:
+//
+//   GET    /todos         all items, in the order they were added
+//   POST   /todos         add {"item": "...", "checked": false}, 201 Created
+//   GET    /todos/{id}    one item
+//   PUT    /todos/{id}    replace the item and checked state
+//   PATCH  /todos/{id}    change the fields that are given
+//   DELETE /todos/{id}    204 No Content
+//
+// An id without an item is 404 Not Found, a body that isn't a valid item is
+// 400 Bad Request.
+
+#[get("/todos")]
+async fn list(app_state: web::Data<TodoAppState>) -> Result<impl Responder> {
+    let todo_list = app_state.todo_list.lock().unwrap().list()?;
+    Ok(web::Json(todo_list.items().to_vec()))
+}
+
+#[post("/todos")]
+async fn create(
+    app_state: web::Data<TodoAppState>,
+    todo: web::Json<NewTodo>,
+) -> Result<impl Responder> {
+    let todo_item = TodoItem::from(todo.into_inner());
+    let todo_item = app_state.todo_list.lock().unwrap().add(todo_item)?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/todos/{}", todo_item.id())))
+        .json(todo_item))
+}
+
+#[get("/todos/{id}")]
+async fn read(
+    app_state: web::Data<TodoAppState>,
+    id: web::Path<u64>,
+) -> Result<impl Responder> {
+    let todo_item = app_state.todo_list.lock().unwrap().get(*id)?;
+    Ok(found(todo_item))
+}
+
+#[put("/todos/{id}")]
+async fn replace(
+    app_state: web::Data<TodoAppState>,
+    id: web::Path<u64>,
+    todo: web::Json<NewTodo>,
+) -> Result<impl Responder> {
+    let changes = TodoChanges::from(todo.into_inner());
+    let todo_item = app_state.todo_list.lock().unwrap().update(*id, changes)?;
+    Ok(found(todo_item))
+}
+
+#[patch("/todos/{id}")]
+async fn change(
+    app_state: web::Data<TodoAppState>,
+    id: web::Path<u64>,
+    changes: web::Json<TodoChanges>,
+) -> Result<impl Responder> {
+    let todo_item = app_state.todo_list.lock().unwrap().update(*id, changes.into_inner())?;
+    Ok(found(todo_item))
+}
+
+#[delete("/todos/{id}")]
+async fn remove(
+    app_state: web::Data<TodoAppState>,
+    id: web::Path<u64>,
+) -> Result<impl Responder> {
+    if app_state.todo_list.lock().unwrap().delete(*id)? {
+        Ok(HttpResponse::NoContent())
+    } else {
+        Ok(HttpResponse::NotFound())
+    }
+}
+
+fn found(todo_item: Option<TodoItem>) -> HttpResponse {
+    match todo_item {
+        Some(todo_item) => HttpResponse::Ok().json(todo_item),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+pub fn routes(config: &mut web::ServiceConfig) {
+    config
+        .service(list)
+        .service(create)
+        .service(read)
+        .service(replace)
+        .service(change)
+        .service(remove);
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::storage;
+
+    fn json(request: TestRequest, body: &'static str) -> TestRequest {
+        request.insert_header(header::ContentType::json()).set_payload(body)
+    }
+
+    #[actix_web::test]
+    async fn crud_on_both_backends() {
+        for storage in storage::backends() {
+            let app_state = web::Data::new(TodoAppState { todo_list: Mutex::new(storage) });
+            let app = test::init_service(App::new().app_data(app_state).configure(routes)).await;
+            let status = |request: TestRequest| {
+                let response = test::call_service(&app, request.to_request());
+                async { response.await.status() }
+            };
+            let body = |request: TestRequest| test::call_and_read_body(&app, request.to_request());
+
+            let request = json(TestRequest::post().uri("/todos"), r#"{"item":"homework"}"#);
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(StatusCode::CREATED, response.status());
+            assert_eq!("/todos/1", response.headers().get(header::LOCATION).unwrap());
+            assert_eq!(r#"{"id":1,"item":"homework","checked":false}"#, test::read_body(response).await);
+            let request = json(TestRequest::post().uri("/todos"), r#"{"item":"cleaning","checked":true}"#);
+            assert_eq!(StatusCode::CREATED, status(request).await);
+
+            let request = json(TestRequest::patch().uri("/todos/1"), r#"{"checked":true}"#);
+            assert_eq!(r#"{"id":1,"item":"homework","checked":true}"#, body(request).await);
+            // PUT replaces the whole item, checked is false when it is left out
+            let request = json(TestRequest::put().uri("/todos/2"), r#"{"item":"cooking"}"#);
+            assert_eq!(r#"{"id":2,"item":"cooking","checked":false}"#, body(request).await);
+
+            assert_eq!(StatusCode::NO_CONTENT, status(TestRequest::delete().uri("/todos/1")).await);
+            assert_eq!(r#"[{"id":2,"item":"cooking","checked":false}]"#, body(TestRequest::get().uri("/todos")).await);
+            assert_eq!(r#"{"id":2,"item":"cooking","checked":false}"#, body(TestRequest::get().uri("/todos/2")).await);
+
+            // Ids aren't reused, and SQLite has none past i64::MAX
+            let beyond = "/todos/9223372036854775808";
+            for request in [
+                TestRequest::get().uri("/todos/1"),
+                TestRequest::delete().uri("/todos/1"),
+                json(TestRequest::patch().uri("/todos/1"), "{}"),
+                json(TestRequest::put().uri("/todos/1"), r#"{"item":"x"}"#),
+                TestRequest::get().uri("/todos/first"),
+                TestRequest::get().uri(beyond),
+                TestRequest::delete().uri(beyond),
+                json(TestRequest::patch().uri(beyond), "{}"),
+                json(TestRequest::put().uri(beyond), r#"{"item":"x"}"#),
+            ] {
+                assert_eq!(StatusCode::NOT_FOUND, status(request).await);
+            }
+            let request = json(TestRequest::post().uri("/todos"), r#"{"checked":true}"#);
+            assert_eq!(StatusCode::BAD_REQUEST, status(request).await);
+        }
+    }
+}
@@ -1,19 +1,34 @@
+mod api;
+mod storage;
 mod todo;
 
+use std::env;
+use std::io;
 use std::str::FromStr;
 use std::sync::Mutex;
 use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder, Result, error};
-use crate::todo::{TodoItem, TodoList};
+use crate::storage::{SqliteStorage, TodoStorage};
+use crate::todo::TodoItem;
+
+// The SQLite database the todos are kept in, created when it doesn't exist
+const DATABASE_VAR: &str = "TODO_DATABASE";
+const DEFAULT_DATABASE: &str = "todos.db";
 
 struct TodoAppState {
-    todo_list: Mutex<TodoList>, // <- Mutex is necessary to mutate safely across threads
+    todo_list: Mutex<Box<dyn TodoStorage>>, // <- Mutex is necessary to mutate safely across threads
 }
 
 
+// The endpoints from before the JSON API under /todos, which are kept working
+// for old clients. Their responses have a Deprecation header and link to what
+// replaces them.
+const DEPRECATION: (&str, &str) = ("Deprecation", "true");
+const SUCCESSOR: (&str, &str) = ("Link", "</todos>; rel=\"successor-version\"");
+
 #[get("/")]
 async fn index(app_state: web::Data<TodoAppState>) -> Result<impl Responder>{
-    let todo_list = app_state.todo_list.lock().unwrap().clone();
-    Ok(web::Json(todo_list))
+    let todo_list = app_state.todo_list.lock().unwrap().list()?;
+    Ok(web::Json(todo_list).customize().insert_header(DEPRECATION).insert_header(SUCCESSOR))
 
 }
 
@@ -22,33 +37,75 @@ async fn add(app_state: web::Data<TodoAppState>, req_body: String) -> Result<imp
     let todo_item =
         TodoItem::from_str(&req_body).map_err(error::ErrorBadRequest)?;
     let mut todo_list = app_state.todo_list.lock().unwrap();
-    todo_list.add(todo_item);
-    Ok(HttpResponse::Ok())
+    todo_list.add(todo_item)?;
+    Ok(HttpResponse::Ok().insert_header(DEPRECATION).insert_header(SUCCESSOR).finish())
 }
 
+// Takes the position of the item in the list, not its id
 #[post("/toggle")]
 async fn toggle(app_state: web::Data<TodoAppState>, req_body: String) -> Result<impl Responder> {
     let todo_number = req_body.parse::<u64>().map_err(error::ErrorBadRequest)?;
     let mut todo_list = app_state.todo_list.lock().unwrap();
     todo_list.toggle(todo_number as usize)?;
-    Ok(HttpResponse::Ok().body(todo_number.to_string()))
+    Ok(HttpResponse::Ok()
+        .insert_header(DEPRECATION)
+        .insert_header(SUCCESSOR)
+        .body(todo_number.to_string()))
+}
+
+fn routes(config: &mut web::ServiceConfig) {
+    config.service(index).service(add).service(toggle).configure(api::routes);
 }
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
+async fn main() -> io::Result<()> {
+    let database = env::var(DATABASE_VAR).unwrap_or_else(|_| String::from(DEFAULT_DATABASE));
+    let storage = SqliteStorage::open(&database)
+        .map_err(|e| io::Error::other(format!("can't open database {database}: {e}")))?;
+
     let app_state = web::Data::new(
         TodoAppState {
-            todo_list: Mutex::new(TodoList::default())
+            todo_list: Mutex::new(Box::new(storage))
         });
 
     HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
-            .service(index)
-            .service(add)
-            .service(toggle)
+            .configure(routes)
     })
         .bind(("127.0.0.1", 8080))?
         .run()
         .await
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn deprecated_handlers_work_on_both_backends() {
+        for storage in storage::backends() {
+            let app_state = web::Data::new(TodoAppState { todo_list: Mutex::new(storage) });
+            let app = test::init_service(App::new().app_data(app_state).configure(routes)).await;
+
+            for item in ["homework", "cleaning"] {
+                let request = test::TestRequest::post().uri("/add").set_payload(item);
+                assert!(test::call_service(&app, request.to_request()).await.status().is_success());
+            }
+            let request = test::TestRequest::post().uri("/toggle").set_payload("1");
+            assert_eq!("1", test::call_and_read_body(&app, request.to_request()).await);
+            let request = test::TestRequest::post().uri("/toggle").set_payload("one");
+            assert!(test::call_service(&app, request.to_request()).await.status().is_client_error());
+
+            let response = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+            assert_eq!("true", response.headers().get("Deprecation").unwrap());
+            let body = test::read_body(response).await;
+            assert_eq!(
+                r#"{"items":[{"id":1,"item":"homework","checked":false},{"id":2,"item":"cleaning","checked":true}]}"#,
+                body
+            );
+        }
+    }
+}
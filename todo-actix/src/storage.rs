@@ -0,0 +1,240 @@
+use std::error::Error;
+use std::path::Path;
+
+use rusqlite::{Connection, OptionalExtension, Row};
+
+use crate::todo::{TodoChanges, TodoItem, TodoList};
+
+// Where the todos are kept. Each item gets an id when it is added, which
+// stays the same until it is deleted and is never given to another item.
+// `toggle` numbers the items by their position in the list instead, which is
+// the order they were added in.
+pub trait TodoStorage: Send {
+    fn list(&self) -> Result<TodoList, Box<dyn Error>>;
+
+    fn get(&self, id: u64) -> Result<Option<TodoItem>, Box<dyn Error>>;
+
+    // Returns the item with its id
+    fn add(&mut self, todo: TodoItem) -> Result<TodoItem, Box<dyn Error>>;
+
+    // Returns the changed item, None when there is no item with `id`
+    fn update(&mut self, id: u64, changes: TodoChanges)
+        -> Result<Option<TodoItem>, Box<dyn Error>>;
+
+    // Returns whether there was an item with `id`
+    fn delete(&mut self, id: u64) -> Result<bool, Box<dyn Error>>;
+
+    // Returns whether the item is checked now
+    fn toggle(&mut self, number: usize) -> Result<bool, Box<dyn Error>>;
+}
+
+// In memory, everything is gone on a restart. Used by the tests.
+impl TodoStorage for TodoList {
+    fn list(&self) -> Result<TodoList, Box<dyn Error>> {
+        Ok(self.clone())
+    }
+
+    fn get(&self, id: u64) -> Result<Option<TodoItem>, Box<dyn Error>> {
+        Ok(TodoList::get(self, id).cloned())
+    }
+
+    fn add(&mut self, todo: TodoItem) -> Result<TodoItem, Box<dyn Error>> {
+        Ok(TodoList::add(self, todo))
+    }
+
+    fn update(&mut self, id: u64, changes: TodoChanges)
+        -> Result<Option<TodoItem>, Box<dyn Error>> {
+        Ok(TodoList::update(self, id, changes).cloned())
+    }
+
+    fn delete(&mut self, id: u64) -> Result<bool, Box<dyn Error>> {
+        Ok(TodoList::delete(self, id))
+    }
+
+    fn toggle(&mut self, number: usize) -> Result<bool, Box<dyn Error>> {
+        TodoList::toggle(self, number)
+    }
+}
+
+// Schema changes, in order. The database remembers how many of them it has
+// had in `user_version`, and the rest are applied when it is opened. Never
+// change one that was released, add a new one instead.
+const MIGRATIONS: &[&str] = &["CREATE TABLE todos (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    item TEXT NOT NULL,
+    checked INTEGER NOT NULL DEFAULT 0
+)"];
+
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    // Creates the database file when there is none yet
+    pub fn open(path: impl AsRef<Path>) -> Result<SqliteStorage, Box<dyn Error>> {
+        SqliteStorage::migrate(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<SqliteStorage, Box<dyn Error>> {
+        SqliteStorage::migrate(Connection::open_in_memory()?)
+    }
+
+    fn migrate(mut connection: Connection) -> Result<SqliteStorage, Box<dyn Error>> {
+        let version: usize =
+            connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > MIGRATIONS.len() {
+            Err(format!("database schema version {version} is newer than this app knows"))?
+        }
+
+        for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", applied + 1)?;
+            transaction.commit()?;
+        }
+        Ok(SqliteStorage { connection })
+    }
+}
+
+// Ids are i64 in SQLite, a larger one has no item
+fn sql_id(id: u64) -> Option<i64> {
+    i64::try_from(id).ok()
+}
+
+// The columns `todo_item` reads
+const COLUMNS: &str = "id, item, checked";
+
+fn todo_item(row: &Row) -> rusqlite::Result<TodoItem> {
+    Ok(TodoItem::new(row.get(1)?, row.get(2)?).with_id(row.get(0)?))
+}
+
+impl TodoStorage for SqliteStorage {
+    fn list(&self) -> Result<TodoList, Box<dyn Error>> {
+        let sql = format!("SELECT {COLUMNS} FROM todos ORDER BY id");
+        let mut statement = self.connection.prepare(&sql)?;
+        let items = statement.query_map([], todo_item)?;
+        Ok(items.collect::<Result<TodoList, _>>()?)
+    }
+
+    fn get(&self, id: u64) -> Result<Option<TodoItem>, Box<dyn Error>> {
+        let Some(id) = sql_id(id) else {
+            return Ok(None);
+        };
+        let sql = format!("SELECT {COLUMNS} FROM todos WHERE id = ?1");
+        Ok(self.connection.query_row(&sql, [id], todo_item).optional()?)
+    }
+
+    fn add(&mut self, todo: TodoItem) -> Result<TodoItem, Box<dyn Error>> {
+        self.connection.execute(
+            "INSERT INTO todos (item, checked) VALUES (?1, ?2)",
+            (todo.item(), todo.checked()),
+        )?;
+        let id = self.connection.last_insert_rowid();
+        Ok(todo.with_id(id.try_into()?))
+    }
+
+    fn update(&mut self, id: u64, changes: TodoChanges)
+        -> Result<Option<TodoItem>, Box<dyn Error>> {
+        let Some(id) = sql_id(id) else {
+            return Ok(None);
+        };
+        let sql = format!(
+            "UPDATE todos SET item = COALESCE(?2, item), checked = COALESCE(?3, checked)
+             WHERE id = ?1 RETURNING {COLUMNS}"
+        );
+        let params = (id, changes.item, changes.checked);
+        Ok(self.connection.query_row(&sql, params, todo_item).optional()?)
+    }
+
+    fn delete(&mut self, id: u64) -> Result<bool, Box<dyn Error>> {
+        let Some(id) = sql_id(id) else {
+            return Ok(false);
+        };
+        Ok(self.connection.execute("DELETE FROM todos WHERE id = ?1", [id])? > 0)
+    }
+
+    fn toggle(&mut self, number: usize) -> Result<bool, Box<dyn Error>> {
+        let checked = self
+            .connection
+            .query_row(
+                "UPDATE todos SET checked = NOT checked
+                 WHERE id = (SELECT id FROM todos ORDER BY id LIMIT 1 OFFSET ?1)
+                 RETURNING checked",
+                [number],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(checked) = checked else {
+            Err("no item found with number")?
+        };
+        Ok(checked)
+    }
+}
+
+// Each backend, new and empty, for the tests that have to pass on all of them
+#[cfg(test)]
+pub fn backends() -> [Box<dyn TodoStorage>; 2] {
+    [Box::new(TodoList::default()), Box::new(SqliteStorage::open_in_memory().unwrap())]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn checked(storage: &dyn TodoStorage) -> Vec<bool> {
+        storage.list().unwrap().items().iter().map(TodoItem::checked).collect()
+    }
+
+    #[test]
+    fn both_backends_number_items_in_order() {
+        for mut storage in backends() {
+            storage.add(TodoItem::from_str("homework").unwrap()).unwrap();
+            storage.add(TodoItem::from_str("cleaning").unwrap()).unwrap();
+
+            assert!(storage.toggle(1).unwrap());
+            assert_eq!(vec![false, true], checked(storage.as_ref()));
+            assert!(!storage.toggle(1).unwrap());
+            assert!(storage.toggle(2).is_err());
+
+            // Ids stay with their items
+            let cooking = storage.add(TodoItem::from_str("cooking").unwrap()).unwrap();
+            assert_eq!(3, cooking.id());
+            assert!(storage.delete(1).unwrap());
+            assert!(!storage.delete(1).unwrap());
+            assert_eq!("cooking", storage.get(3).unwrap().unwrap().item());
+            assert!(storage.get(1).unwrap().is_none());
+            assert_eq!(4, storage.add(TodoItem::from_str("shopping").unwrap()).unwrap().id());
+
+            let changes = TodoChanges { item: Some(String::from("dishes")), checked: None };
+            let dishes = storage.update(3, changes).unwrap().unwrap();
+            assert_eq!((3, "dishes", false), (dishes.id(), dishes.item(), dishes.checked()));
+            assert!(storage.update(1, TodoChanges::default()).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn todos_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("todos.db");
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        storage.add(TodoItem::from_str("homework").unwrap()).unwrap();
+        storage.toggle(0).unwrap();
+        drop(storage);
+
+        // Migrations that were applied aren't applied again
+        let storage = SqliteStorage::open(&path).unwrap();
+        let items = storage.list().unwrap();
+        assert_eq!(vec!["homework"], items.items().iter().map(TodoItem::item).collect::<Vec<_>>());
+        assert_eq!(vec![true], checked(&storage));
+    }
+
+    #[test]
+    fn newer_schema_is_refused() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.pragma_update(None, "user_version", MIGRATIONS.len() + 1).unwrap();
+        assert!(SqliteStorage::migrate(connection).is_err());
+    }
+}
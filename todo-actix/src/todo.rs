@@ -2,38 +2,133 @@ use std::error::Error;
 use std::str::FromStr;
 use std::string::ParseError;
 
-use serde::{Serialize};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Serialize, Clone)]
 pub struct TodoList{
-    items: Vec<TodoItem>
+    items: Vec<TodoItem>,
+    // Ids are never reused, not even those of deleted items
+    #[serde(skip)]
+    last_id: u64,
 }
 
 #[derive(Debug, Serialize, Clone)]
 pub struct TodoItem{
+    // Given by the storage when the item is added, 0 until then
+    id: u64,
     item: String,
     checked: bool
 }
 
+// The body of `POST /todos` and `PUT /todos/{id}`
+#[derive(Debug, Deserialize)]
+pub struct NewTodo {
+    pub item: String,
+    #[serde(default)]
+    pub checked: bool,
+}
+
+// The body of `PATCH /todos/{id}`, fields that are left out stay as they are
+#[derive(Debug, Default, Deserialize)]
+pub struct TodoChanges {
+    pub item: Option<String>,
+    pub checked: Option<bool>,
+}
+
+impl From<NewTodo> for TodoChanges {
+    fn from(todo: NewTodo) -> Self {
+        TodoChanges { item: Some(todo.item), checked: Some(todo.checked) }
+    }
+}
+
 impl FromStr for TodoItem {
     type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(TodoItem{item:s.to_string(), checked:false })
+        Ok(TodoItem{id: 0, item:s.to_string(), checked:false })
+    }
+}
+
+impl From<NewTodo> for TodoItem {
+    fn from(todo: NewTodo) -> Self {
+        TodoItem::new(todo.item, todo.checked)
     }
 }
 
 impl TodoItem {
+    pub fn new(item: String, checked: bool) -> TodoItem {
+        TodoItem { id: 0, item, checked }
+    }
+
+    pub fn with_id(self, id: u64) -> TodoItem {
+        TodoItem { id, ..self }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn item(&self) -> &str {
+        &self.item
+    }
+
+    pub fn checked(&self) -> bool {
+        self.checked
+    }
+
     fn toggle(&mut self) {
         self.checked = !self.checked;
     }
+
+    fn apply(&mut self, changes: TodoChanges) {
+        if let Some(item) = changes.item {
+            self.item = item;
+        }
+        if let Some(checked) = changes.checked {
+            self.checked = checked;
+        }
+    }
+}
+
+impl FromIterator<TodoItem> for TodoList {
+    fn from_iter<I: IntoIterator<Item = TodoItem>>(items: I) -> Self {
+        let items: Vec<TodoItem> = items.into_iter().collect();
+        let last_id = items.iter().map(TodoItem::id).max().unwrap_or(0);
+        TodoList { items, last_id }
+    }
 }
 
 impl TodoList {
-    pub fn add(&mut self, todo: TodoItem) {
-        self.items.push(todo)
+    pub fn items(&self) -> &[TodoItem] {
+        &self.items
+    }
+
+    // Returns the item with its new id
+    pub fn add(&mut self, todo: TodoItem) -> TodoItem {
+        self.last_id += 1;
+        let todo = todo.with_id(self.last_id);
+        self.items.push(todo.clone());
+        todo
+    }
+
+    pub fn get(&self, id: u64) -> Option<&TodoItem> {
+        self.items.iter().find(|item| item.id == id)
     }
 
+    pub fn update(&mut self, id: u64, changes: TodoChanges) -> Option<&TodoItem> {
+        let item = self.items.iter_mut().find(|item| item.id == id)?;
+        item.apply(changes);
+        Some(item)
+    }
+
+    // Returns whether there was an item with `id`
+    pub fn delete(&mut self, id: u64) -> bool {
+        let count = self.items.len();
+        self.items.retain(|item| item.id != id);
+        self.items.len() < count
+    }
+
+    // `number` is the position of the item in the list
     pub fn toggle(&mut self, number: usize) -> Result<bool, Box<dyn Error>>{
         let Some(item) = self.items.get_mut(number) else {
             Err("no item found with number")?
@@ -58,7 +153,7 @@ mod tests {
         todo_list.add(TodoItem::from_str("cleaning").unwrap());
         assert_eq!(2, todo_list.items.len());
 
-        assert_eq!("homework", todo_list.items.get(0).unwrap().item);
+        assert_eq!("homework", todo_list.items.first().unwrap().item);
         assert_eq!("cleaning", todo_list.items.get(1).unwrap().item);
 
     }
@@ -75,7 +170,7 @@ mod tests {
 
         assert!(result_error.is_err());
         // All unchecked
-        assert!(todo_list.items.iter().all(|i| i.checked == false));
+        assert!(todo_list.items.iter().all(|i| !i.checked));
 
     }
 
@@ -86,7 +181,7 @@ mod tests {
         todo_list.add(TodoItem::from_str("").unwrap());
         assert_eq!(1, todo_list.items.len());
         // All unchecked
-        assert!(todo_list.items.iter().all(|i| i.checked == false));
+        assert!(todo_list.items.iter().all(|i| !i.checked));
 
     }
 
@@ -97,22 +192,41 @@ mod tests {
         todo_list.add(TodoItem::from_str("cleaning").unwrap());
         todo_list.add(TodoItem::from_str("cooking").unwrap());
         // All unchecked
-        assert!(todo_list.items.iter().all(|i| i.checked == false));
+        assert!(todo_list.items.iter().all(|i| !i.checked));
 
         todo_list.toggle(1).unwrap();
-        assert_eq!(false, todo_list.items.get(0).unwrap().checked);
-        assert_eq!(true, todo_list.items.get(1).unwrap().checked);
-        assert_eq!(false, todo_list.items.get(2).unwrap().checked);
+        assert!(!todo_list.items.first().unwrap().checked);
+        assert!(todo_list.items.get(1).unwrap().checked);
+        assert!(!todo_list.items.get(2).unwrap().checked);
 
         todo_list.toggle(0).unwrap();
-        assert_eq!(true, todo_list.items.get(0).unwrap().checked);
-        assert_eq!(true, todo_list.items.get(1).unwrap().checked);
-        assert_eq!(false, todo_list.items.get(2).unwrap().checked);
+        assert!(todo_list.items.first().unwrap().checked);
+        assert!(todo_list.items.get(1).unwrap().checked);
+        assert!(!todo_list.items.get(2).unwrap().checked);
 
         todo_list.toggle(2).unwrap();
-        assert_eq!(true, todo_list.items.get(0).unwrap().checked);
-        assert_eq!(true, todo_list.items.get(1).unwrap().checked);
-        assert_eq!(true, todo_list.items.get(2).unwrap().checked);
+        assert!(todo_list.items.first().unwrap().checked);
+        assert!(todo_list.items.get(1).unwrap().checked);
+        assert!(todo_list.items.get(2).unwrap().checked);
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_stable_ids() {
+        let mut todo_list = TodoList::default();
+        let homework = todo_list.add(TodoItem::from_str("homework").unwrap());
+        let cleaning = todo_list.add(TodoItem::from_str("cleaning").unwrap());
+        assert_eq!((1, 2), (homework.id(), cleaning.id()));
+
+        assert!(todo_list.delete(1));
+        assert!(!todo_list.delete(1));
+        // Deleting doesn't change the id of other items, or free its own
+        assert_eq!("cleaning", todo_list.get(2).unwrap().item);
+        assert_eq!(3, todo_list.add(TodoItem::from_str("cooking").unwrap()).id());
+
+        let changes = TodoChanges { checked: Some(true), ..TodoChanges::default() };
+        let cooking = todo_list.update(3, changes).unwrap();
+        assert_eq!(("cooking", true), (cooking.item(), cooking.checked()));
+        assert!(todo_list.update(1, TodoChanges::default()).is_none());
+    }
+}